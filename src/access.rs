@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use rocket::Request;
+use serde::Deserialize;
+use crate::config;
+
+// shown instead of the hash when redirects are sent to api users
+pub(crate) const HIDDEN_PASSWORD: &str = "********";
+// entries are cleaned up once there are more keys than this, the oldest are dropped when all are recent
+pub(crate) const MAX_TRACKED: usize = 10000;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct AccessConfig {
+    // wrong passwords for one link from one address before it gets locked
+    pub(crate) access_max_attempts: u32,
+    // seconds the link stays locked for that address
+    pub(crate) access_lockout_secs: u64,
+    // wrong passwords for one link from all addresses before it gets locked for everyone, 0 turns it off
+    // anyone can then lock the link for visitors knowing the password too
+    pub(crate) access_max_link_attempts: u32,
+    // proxies whose `X-Real-IP` header is used as the client address, otherwise the connecting address is used
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            access_max_attempts: 5,
+            access_lockout_secs: 300,
+            access_max_link_attempts: 0,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+struct Attempts {
+    failed: u32,
+    since: Instant,
+}
+
+// failed attempts per link and address, kept in memory only
+pub(crate) struct Throttle {
+    max_attempts: u32,
+    lockout: Duration,
+    attempts: HashMap<String, Attempts>,
+}
+
+static THROTTLE: LazyLock<Mutex<Throttle>> = LazyLock::new(|| Mutex::new(Throttle::new(&config::load::<AccessConfig>())));
+// failures per link from any address, changing addresses doesn't give more tries, none when turned off
+static LINK_THROTTLE: LazyLock<Option<Mutex<Throttle>>> = LazyLock::new(|| {
+    let conf = config::load::<AccessConfig>();
+    (conf.access_max_link_attempts > 0).then(|| Mutex::new(Throttle::new(&AccessConfig { access_max_attempts: conf.access_max_link_attempts, ..conf })))
+});
+
+impl Throttle {
+    pub(crate) fn new(conf: &AccessConfig) -> Self {
+        Self {
+            max_attempts: conf.access_max_attempts.max(1),
+            lockout: Duration::from_secs(conf.access_lockout_secs),
+            attempts: HashMap::new(),
+        }
+    }
+
+    // seconds until the key can try again, none when it can try now
+    pub(crate) fn locked(&self, key: &str, now: Instant) -> Option<u64> {
+        let a = self.attempts.get(key)?;
+        let passed = now.saturating_duration_since(a.since);
+        if a.failed >= self.max_attempts && passed < self.lockout {
+            Some((self.lockout - passed).as_secs().max(1))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn failed(&mut self, key: &str, now: Instant) {
+        if self.attempts.len() >= MAX_TRACKED && !self.attempts.contains_key(key) {
+            let lockout = self.lockout;
+            self.attempts.retain(|_, a| now.saturating_duration_since(a.since) < lockout);
+        }
+        if self.attempts.len() >= MAX_TRACKED && !self.attempts.contains_key(key) {
+            // a tenth of the oldest entries makes room for a while
+            let mut oldest: Vec<(Instant, String)> = self.attempts.iter().map(|(k, a)| (a.since, k.clone())).collect();
+            oldest.sort();
+            for (_, k) in oldest.into_iter().take(MAX_TRACKED / 10) {
+                self.attempts.remove(&k);
+            }
+        }
+        let lockout = self.lockout;
+        let a = self.attempts.entry(key.to_string()).or_insert(Attempts { failed: 0, since: now });
+        // attempts older than the lockout don't count anymore
+        if now.saturating_duration_since(a.since) >= lockout {
+            *a = Attempts { failed: 0, since: now };
+        }
+        a.failed += 1;
+    }
+
+    pub(crate) fn succeeded(&mut self, key: &str) {
+        self.attempts.remove(key);
+    }
+}
+
+pub(crate) enum Access {
+    Granted,
+    Denied,
+    // seconds until next try
+    Locked(u64),
+}
+
+// checks password against the hash of the link, counting failures per `link` and per `link` and `client`
+pub(crate) fn verify(link: &str, client: &str, password: &str, hash: &str) -> Access {
+    let now = Instant::now();
+    let key = format!("{}/{}", link, client);
+    let locked = THROTTLE.lock().ok().and_then(|t| t.locked(&key, now));
+    let locked = locked.or_else(|| LINK_THROTTLE.as_ref()?.lock().ok()?.locked(link, now));
+    if let Some(secs) = locked {
+        return Access::Locked(secs);
+    }
+    let ok = bcrypt::verify(password, hash).unwrap_or_else(|e| {
+        println!("{:?}", e);
+        false
+    });
+    if let Ok(mut throttle) = THROTTLE.lock() {
+        if ok {
+            throttle.succeeded(&key);
+        } else {
+            throttle.failed(&key, now);
+        }
+    }
+    if !ok {
+        if let Some(Ok(mut throttle)) = LINK_THROTTLE.as_ref().map(Mutex::lock) {
+            throttle.failed(link, now);
+        }
+    }
+    if ok { Access::Granted } else { Access::Denied }
+}
+
+// address of the client, forwarded address only from `trusted_proxies`
+pub(crate) fn client_ip(req: &Request<'_>) -> Option<IpAddr> {
+    let remote = req.remote().map(|r| r.ip());
+    match remote {
+        Some(r) if config::load::<AccessConfig>().trusted_proxies.contains(&r) => req.real_ip().or(remote),
+        _ => remote,
+    }
+}
@@ -1,2 +1,3 @@
 // reexport
+pub(crate) mod openapi;
 pub(crate) mod v1;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use rocket::{Build, Rocket};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::{Info, OpenApi},
+    rapidoc::{GeneralConfig, RapiDocConfig, make_rapidoc},
+    settings::{OpenApiSettings, UrlObject},
+    swagger_ui::{SwaggerUIConfig, make_swagger_ui},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use crate::Domain;
+use crate::database::{Auth, Permission};
+
+// `ObjectId` as serialized into json e.g. {"$oid": "62ef10543db77254ebbf48a3"}
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ObjectIdSchema {
+    #[serde(rename = "$oid")]
+    oid: String,
+}
+
+// `DateTime` as serialized into json e.g. {"$date": {"$numberLong": "1660989600000"}}
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct DateTimeSchema {
+    #[serde(rename = "$date")]
+    date: DateTimeBody,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct DateTimeBody {
+    #[serde(rename = "$numberLong")]
+    number_long: String,
+}
+
+// spec with models which are not directly used by routes (they are sent inside `Response.response`)
+pub(crate) fn models_spec(settings: &OpenApiSettings) -> OpenApi {
+    let mut gen = OpenApiGenerator::new(settings);
+    gen.json_schema::<Domain>();
+    gen.json_schema::<Auth>();
+    gen.json_schema::<Permission>();
+    let mut spec = gen.into_openapi();
+    spec.info = Info {
+        title: "RustRedirect".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        description: Some("Every endpoint takes auth name and password as json body.".to_string()),
+        ..Default::default()
+    };
+    spec
+}
+
+// mounts swagger ui on /api/docs and rapidoc on /api/rapidoc, both reading /api/openapi.json
+pub(crate) fn mount_docs(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket
+        .mount("/api/docs", make_swagger_ui(&SwaggerUIConfig {
+            url: "../openapi.json".to_string(),
+            ..Default::default()
+        }))
+        .mount("/api/rapidoc", make_rapidoc(&RapiDocConfig {
+            general: GeneralConfig {
+                spec_urls: vec![UrlObject::new("General", "../openapi.json")],
+                ..Default::default()
+            },
+            ..Default::default()
+        }))
+}
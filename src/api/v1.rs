@@ -1,53 +1,161 @@
 use mongodb::{
-    Collection,
-    bson::{doc, Document},
+    bson::{doc, DateTime, Document},
+    error::{ErrorKind, WriteFailure},
+    options::FindOneOptions,
 };
-use rand::{
-    SeedableRng,
-    distributions::{Alphanumeric, DistString},
-};
-use regex::Regex;
 use rocket::{
     Build,
     Rocket,
-    futures::TryStreamExt,
     serde::json::Json
 };
+use rocket_okapi::{
+    mount_endpoints_and_merged_docs,
+    openapi,
+    openapi_get_routes_spec,
+    settings::OpenApiSettings,
+};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
-use crate::{AUTH_COLLECTION, DOMAINS_COLLECTION, Domain, connect, some_return, ok_return, add_and};
-use crate::database::{Auth, Permission};
+use crate::{AUTH_COLLECTION, DOMAINS_COLLECTION, Domain, config, connect, some_return, ok_return, add_and};
+use crate::api::openapi::{models_spec, mount_docs};
+use crate::database::{Auth, Permission, all_names};
+use crate::chains::{ChainConfig, DbLinks, LinkName, Links, resolve_chain};
+use crate::history::renamed_aliases;
+use crate::host_rules::{HostRules, host_rules};
+use crate::random_names::random_name;
+use crate::redirect::{HostsConfig, build_params, namespace_bson};
+use crate::validation::{NameConfig, UrlConfig, validate_name, validate_url};
+use listing::{AuthQuery, DomainQuery, split_tags};
+
+mod bulk;
+mod clicks;
+mod export;
+mod history;
+mod hosts;
+mod listing;
+mod patterns;
+mod qr;
+mod schedule;
+mod trash;
+mod targets;
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 struct Response {
     success: bool,
     response: Value,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, JsonSchema, Clone)]
 struct PreAuth {
     name: String,
     password: String,
 }
 
+// query params for editing a redirect, params which aren't sent stay as they are
+// e.g. /api/v1/redirect/edit?name=docs&domain=https://example.com&tags=docs
+#[derive(FromForm, JsonSchema)]
+struct EditQuery {
+    /// Name of the redirect to edit
+    name: Option<String>,
+    /// New name of the redirect
+    newname: Option<String>,
+    /// New destination
+    domain: Option<String>,
+    /// Comma separated, replace current tags
+    tags: Option<String>,
+    /// Add remaining path and query to destination
+    forward: Option<bool>,
+    /// Short host the redirect belongs to, empty for global redirects
+    namespace: Option<String>,
+    /// Password asked before redirecting, empty removes it
+    access_password: Option<String>,
+    /// Shown on the preview page, empty removes it
+    title: Option<String>,
+    /// Shown on the preview page, empty removes it
+    description: Option<String>,
+    /// Show preview with countdown before redirecting
+    interstitial: Option<bool>,
+    /// Query added to destination like `utm_source=news&utm_medium=email`, empty removes it
+    params: Option<String>,
+    /// Old name keeps redirecting here when renamed
+    keep_alias: Option<bool>,
+}
+
+// query params for creating a redirect
+// e.g. /api/v1/redirect/create?name=docs&domain=https://example.com&title=Docs
+#[derive(FromForm, JsonSchema)]
+struct CreateQuery {
+    name: Option<String>,
+    domain: Option<String>,
+    /// Comma separated
+    tags: Option<String>,
+    /// Add remaining path and query to destination
+    forward: Option<bool>,
+    /// Short host the redirect belongs to, empty for global redirects
+    namespace: Option<String>,
+    /// Password asked before redirecting
+    access_password: Option<String>,
+    /// Shown on the preview page
+    title: Option<String>,
+    /// Shown on the preview page
+    description: Option<String>,
+    /// Show preview with countdown before redirecting
+    interstitial: Option<bool>,
+    /// Query added to destination like `utm_source=news`, keys destination already has are kept
+    params: Option<String>,
+    /// Sets `utm_source` of params
+    utm_source: Option<String>,
+    /// Sets `utm_medium` of params
+    utm_medium: Option<String>,
+    /// Sets `utm_campaign` of params
+    utm_campaign: Option<String>,
+    /// Sets `utm_term` of params
+    utm_term: Option<String>,
+    /// Sets `utm_content` of params
+    utm_content: Option<String>,
+}
+
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_DESCRIPTION_LENGTH: usize = 2000;
+const MAX_PARAMS_LENGTH: usize = 1000;
+// random names inserted before giving up when each was taken meanwhile
+const RANDOM_INSERT_TRIES: usize = 3;
+
 pub(crate) fn mount_v1(rocket: Rocket<Build>) -> Rocket<Build> {
-    let rocket = rocket.mount(
-        "/api/v1/redirect",
-        routes![
+    let mut rocket = rocket;
+    let settings = OpenApiSettings::default();
+    // mounts routes and serves their docs on /api/openapi.json
+    mount_endpoints_and_merged_docs! {
+        rocket, "/api", settings,
+        "/v1/redirect" => openapi_get_routes_spec![
+            settings:
             check_domains,
             create_redirect,
             edit_redirect,
             remove_redirect,
+            history::list_history,
+            history::rollback_redirect,
+            trash::restore_redirect,
+            trash::purge_redirect,
             random_redirect,
+            bulk::bulk_redirect,
+            bulk::import_redirects,
+            export::export_redirects,
+            qr::qr_redirect,
+            targets::set_targets,
+            targets::set_variants,
+            schedule::set_schedule,
+            clicks::click_stats,
             i_create_post,
             i_edit_put,
             i_delete_delete,
             i_random_post,
+            i_bulk_post,
+            i_import_post,
         ],
-    );
-    let rocket = rocket.mount(
-        "/api/v1/auth",
-        routes![
+        "/v1/auth" => openapi_get_routes_spec![
+            settings:
             list_auth,
             create_auth,
             edit_auth,
@@ -56,38 +164,59 @@ pub(crate) fn mount_v1(rocket: Rocket<Build>) -> Rocket<Build> {
             i_edit_put,
             i_delete_delete,
         ],
-    );
-    rocket
+        "/v1/hosts" => openapi_get_routes_spec![
+            settings:
+            hosts::list_hosts,
+            hosts::create_host,
+            hosts::delete_host,
+            i_create_post,
+            i_delete_delete,
+        ],
+        "/v1/patterns" => openapi_get_routes_spec![
+            settings:
+            patterns::list_patterns,
+            patterns::create_pattern,
+            patterns::delete_pattern,
+            i_create_post,
+            i_delete_delete,
+        ],
+        "" => (vec![], models_spec(&settings)),
+    };
+    mount_docs(rocket).register("/api", catchers![api_not_found, api_server_error])
 }
 
 ////////////
 // DOMAINS
 ////////////
 
-#[get("/", data = "<user>")]
-async fn check_domains(user: Json<PreAuth>) -> Json<Response> {
+#[openapi(tag = "Redirect")]
+#[get("/?<query..>", data = "<user>")]
+async fn check_domains(query: DomainQuery, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
         Ok(a) => a,
         Err(e) => return e,
     };
-    let document;
-    if auth.permission.can_list() {
-        document = None;
-    } else if auth.permission.can_own() {
-        document = Some(doc! { "owner": auth._id });
-    } else {
-        return Response::PERMISSIONS_TOO_LOW().json();
-    }
+    let document = match domain_scope(&auth) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+    let document = match query.filter(document) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
     let conn = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
-    let cursor = ok_return!(conn.find(document, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
-    let collected: Vec<Domain> = ok_return!(cursor.try_collect().await, Response::DATABASE_WHILST_TRYING_TO_COLLECT().json());
-    let collected = ok_return!(serde_json::to_value(collected), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    let page = match query.page(&conn, document).await {
+        Ok(p) => p.map(Domain::hide_password),
+        Err(e) => return e,
+    };
+    let page = ok_return!(serde_json::to_value(page), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
     Response {
         success: true,
-        response: collected,
+        response: page,
     }.json()
 }
 
+#[openapi(tag = "Redirect")]
 #[post("/random?<domain>", data = "<user>")]
 async fn random_redirect(domain: Option<String>, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
@@ -95,71 +224,102 @@ async fn random_redirect(domain: Option<String>, user: Json<PreAuth>) -> Json<Re
         Err(e) => return e,
     };
     let domain = some_return!(domain, Response::USER_DID_NOT_PROVIDE_PARAM("domain").json());
-    let domain_regex = Regex::new(r#"https?://[^-][A-z\d-]{1,63}(?:\.[^-][A-z\d-]+){0,63}\.[A-z]{2,}"#).unwrap();
-    if !domain_regex.is_match(&domain) {
-        return Response::NOT_ALLOWED_DOMAIN_FORMAT().json();
-    }
+    let domain = match check_domain_format(&domain, &*host_rules().await) {
+        Ok(d) => d,
+        Err(e) => return e.json(),
+    };
     let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
     if auth.permission.can_random() {
-        let name = match get_check_random(&db, Alphanumeric.sample_string(&mut rand::rngs::SmallRng::from_entropy(), 8), 3).await {
-            Ok(o) => o,
-            Err(e) => return e
-        };
-        let res = db.insert_one(
-            Domain {
-                _id: Default::default(),
-                name: name.clone(),
-                domain: domain.clone(),
-                owner: auth._id,
-            }, None).await;
-        return match res {
-            Ok(_) => Response::new(true, &format!("Created random redirect to '{}' named '{}'.", domain, name)).json(),
-            Err(_) => Response::COULD_NOT("create", "random redirect").json()
-        };
+        // the free name can be taken by another request before it's inserted
+        for _ in 0..RANDOM_INSERT_TRIES {
+            let name = some_return!(random_name(&db).await, Response::COULD_NOT("create", "random redirect").json());
+            let domain = match check_chain(&name, &None, &domain, &DbLinks { col: &db, renamed: None }).await {
+                Ok(d) => d,
+                Err(e) => return e.json(),
+            };
+            match db.insert_one(Domain::new(name.clone(), domain.clone(), auth._id), None).await {
+                Ok(_) => return Response::new(true, &format!("Created random redirect to '{}' named '{}'.", domain, name)).json(),
+                Err(e) if is_duplicate(&e) => continue,
+                Err(_) => return Response::COULD_NOT("create", "random redirect").json()
+            }
+        }
+        Response::COULD_NOT("create", "random redirect").json()
     } else {
         Response::PERMISSIONS_TOO_LOW().json()
     }
 }
 
-#[async_recursion::async_recursion]
-async fn get_check_random(db: &Collection<Domain>, name: String, tries: u32) -> Result<String, Json<Response>> {
-    if tries == 0 {
-        return Err(Response::COULD_NOT("create", "random redirect").json());
-    }
-    let dom = ok_return!(db.find_one(doc! { "name": name.clone() }, None).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
-    return match dom {
-        Some(_) => get_check_random(db, Alphanumeric.sample_string(&mut rand::rngs::SmallRng::from_entropy(), 8), tries - 1).await,
-        None => Ok(name)
-    };
-}
-
-#[post("/create?<name>&<domain>", data = "<user>")]
-async fn create_redirect(name: Option<String>, domain: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+#[openapi(tag = "Redirect")]
+#[post("/create?<query..>", data = "<user>")]
+async fn create_redirect(query: CreateQuery, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
         Ok(a) => a,
         Err(e) => return e,
     };
+    let CreateQuery {
+        name, domain, tags, forward, namespace, access_password, title, description, interstitial,
+        params, utm_source, utm_medium, utm_campaign, utm_term, utm_content,
+    } = query;
     let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
     let domain = some_return!(domain, Response::USER_DID_NOT_PROVIDE_PARAM("domain").json());
-    let domain_regex = Regex::new(r#"https?://[^-][A-z\d-]{1,63}(?:\.[^-][A-z\d-]+){0,63}\.[A-z]{2,}"#).unwrap();
-    if !domain_regex.is_match(&domain) {
-        return Response::NOT_ALLOWED_DOMAIN_FORMAT().json();
+    if let Err(e) = check_name(&name) {
+        return e.json();
     }
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let domain = match check_domain_format(&domain, &*host_rules().await) {
+        Ok(d) => d,
+        Err(e) => return e.json(),
+    };
+    let access_password = match hash_access_password(access_password) {
+        Ok(p) => p,
+        Err(e) => return e.json(),
+    };
+    if let Err(e) = check_text("title", &title, MAX_TITLE_LENGTH).and(check_text("description", &description, MAX_DESCRIPTION_LENGTH)) {
+        return e.json();
+    }
+    let utm = [
+        ("utm_source", &utm_source),
+        ("utm_medium", &utm_medium),
+        ("utm_campaign", &utm_campaign),
+        ("utm_term", &utm_term),
+        ("utm_content", &utm_content),
+    ];
+    let utm: Vec<(&str, &str)> = utm.iter().filter_map(|(k, v)| v.as_deref().map(|v| (*k, v))).collect();
+    let params = match check_params(params.as_deref(), &utm) {
+        Ok(p) => p,
+        Err(e) => return e.json(),
+    };
     if auth.permission.can_own() {
         let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
-        let dom: Option<Domain> = ok_return!(db.find_one(doc! { "name" : name.clone() }, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
-        if dom.is_some() {
-            return Response::EXIST("Redirect", "already").json();
+        let dom: Option<Domain> = ok_return!(db.find_one(name_taken(&name, &namespace), name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+        match dom {
+            Some(d) if d.deleted.is_some() => return Response::IN_TRASH().json(),
+            Some(_) => return Response::EXIST("Redirect", "already").json(),
+            None => {}
         }
+        let domain = match check_chain(&name, &namespace, &domain, &DbLinks { col: &db, renamed: None }).await {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
         let res = db.insert_one(
             Domain {
-                _id: Default::default(),
-                name: name.clone(),
-                domain: domain.clone(),
-                owner: auth._id,
+                tags: tags.as_deref().map(split_tags).unwrap_or_default(),
+                forward: forward.unwrap_or(false),
+                namespace,
+                access_password: access_password.flatten(),
+                title: title.filter(|t| !t.is_empty()),
+                description: description.filter(|d| !d.is_empty()),
+                interstitial: interstitial.unwrap_or(false),
+                params: params.flatten(),
+                ..Domain::new(name.clone(), domain.clone(), auth._id)
             }, None).await;
         return match res {
             Ok(_) => Response::new(true, &format!("Created redirect to '{}' named '{}'.", domain, name)).json(),
+            // created by someone else since the check above
+            Err(e) if is_duplicate(&e) => Response::EXIST("Redirect", "already").json(),
             Err(_) => Response::COULD_NOT("create", "redirect").json()
         };
     } else {
@@ -167,42 +327,103 @@ async fn create_redirect(name: Option<String>, domain: Option<String>, user: Jso
     }
 }
 
-#[put("/edit?<name>&<newname>&<domain>", data = "<user>")]
-async fn edit_redirect(name: Option<String>, newname: Option<String>, domain: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+#[openapi(tag = "Redirect")]
+#[put("/edit?<query..>", data = "<user>")]
+async fn edit_redirect(query: EditQuery, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
         Ok(a) => a,
         Err(e) => return e,
     };
+    let EditQuery { name, newname, domain, tags, forward, namespace, access_password, title, description, interstitial, params, keep_alias } = query;
     let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
-    let domain_regex = Regex::new(r#"https?://[^-][A-z\d-]{1,63}(?:\.[^-][A-z\d-]+){0,63}\.[A-z]{2,}"#).unwrap();
-    if domain.is_some() && !domain_regex.is_match(&domain.clone().unwrap()) {
-        return Response::NOT_ALLOWED_DOMAIN_FORMAT().json();
+    if let Some(Err(e)) = newname.as_deref().map(check_name) {
+        return e.json();
+    }
+    let domain = match domain {
+        None => None,
+        Some(d) => match check_domain_format(&d, &*host_rules().await) {
+            Ok(d) => Some(d),
+            Err(e) => return e.json(),
+        },
+    };
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let access_password = match hash_access_password(access_password) {
+        Ok(p) => p,
+        Err(e) => return e.json(),
+    };
+    if let Err(e) = check_text("title", &title, MAX_TITLE_LENGTH).and(check_text("description", &description, MAX_DESCRIPTION_LENGTH)) {
+        return e.json();
     }
-    let search_name = match get_search(auth, &name) {
+    let params = match check_params(params.as_deref(), &[]) {
+        Ok(p) => p,
+        Err(e) => return e.json(),
+    };
+    let search_name = match get_search(auth.clone(), &name, &namespace) {
         Ok(o) => o,
         Err(e) => return e
     };
     let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
     if let Some(newname) = newname.clone() {
-        let existing_domain: Option<Domain> = ok_return!(db.find_one(doc! { "name" : newname }, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
-        if existing_domain.is_some() {
+        let existing_domain: Option<Domain> = ok_return!(db.find_one(name_taken(&newname, &namespace), name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+        // changing only case of the name finds the redirect itself
+        if existing_domain.filter(|d| d.name != name).is_some() {
             return Response::EXIST("Domain with the new name", "already").json();
         }
     }
-    let dom: Option<Domain> = ok_return!(db.find_one(search_name, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom: Option<Domain> = ok_return!(db.find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
     let dom = match dom {
         None => return Response::EXIST("Redirect", "doesn't").json(),
         Some(d) => d
     };
+    let domain = match (&newname, domain) {
+        (None, None) => None,
+        (_, domain) => {
+            let links = DbLinks {
+                col: &db,
+                renamed: newname.as_ref().map(|_| LinkName::new(&dom.namespace, &name)),
+            };
+            let checked = check_chain(newname.as_ref().unwrap_or(&name), &dom.namespace, domain.as_ref().unwrap_or(&dom.domain), &links).await;
+            match checked {
+                // flattening can change destination even when only name was edited
+                Ok(d) if domain.is_some() || d != dom.domain => Some(d),
+                Ok(_) => None,
+                Err(e) => return e.json(),
+            }
+        }
+    };
+    let tags = tags.as_deref().map(split_tags);
+    let aliases = match &newname {
+        Some(newname) => renamed_aliases(&dom.aliases, &name, newname, keep_alias.unwrap_or(false)),
+        None => dom.aliases.clone(),
+    };
+    let previous = dom.clone();
     let res = db
         .update_one(
             doc! { "_id" : dom._id },
-            doc! { "$set": { "name": newname.clone().unwrap_or(name.clone()), "domain": domain.clone().unwrap_or(dom.domain.clone()) } },
+            doc! {
+                "$set": {
+                    "name": newname.clone().unwrap_or(name.clone()),
+                    "domain": domain.clone().unwrap_or(dom.domain.clone()),
+                    "tags": tags.clone().unwrap_or(dom.tags.clone()),
+                    "forward": forward.unwrap_or(dom.forward),
+                    "access_password": access_password.clone().unwrap_or(dom.access_password.clone()),
+                    "title": title.clone().map(|t| Some(t).filter(|t| !t.is_empty())).unwrap_or(dom.title.clone()),
+                    "description": description.clone().map(|d| Some(d).filter(|d| !d.is_empty())).unwrap_or(dom.description.clone()),
+                    "interstitial": interstitial.unwrap_or(dom.interstitial),
+                    "params": params.clone().unwrap_or(dom.params.clone()),
+                    "names": all_names(&newname.clone().unwrap_or(name.clone()), &aliases),
+                    "aliases": &aliases
+                }
+            },
             None)
         .await;
     match res {
         Ok(m) if m.modified_count > 0 => {
-            if newname.is_none() && domain.is_none() {
+            if newname.is_none() && domain.is_none() && tags.is_none() && forward.is_none() && access_password.is_none()
+                && title.is_none() && description.is_none() && interstitial.is_none() && params.is_none() {
                 return Response::NOTHING_CHANGED().json();
             }
             let mut str = "".to_string();
@@ -211,33 +432,71 @@ async fn edit_redirect(name: Option<String>, newname: Option<String>, domain: Op
             }
             if let Some(domain) = domain {
                 add_and!(str);
-                str += &format!("permission '{}' -> '{}'", dom.domain, domain);
+                str += &format!("domain '{}' -> '{}'", dom.domain, domain);
+            }
+            if let Some(tags) = tags {
+                add_and!(str);
+                str += &format!("tags '{}' -> '{}'", dom.tags.join(","), tags.join(","));
+            }
+            if let Some(forward) = forward {
+                add_and!(str);
+                str += &format!("forward '{}' -> '{}'", dom.forward, forward);
+            }
+            if let Some(password) = access_password {
+                add_and!(str);
+                str += if password.is_some() { "access password set" } else { "access password removed" };
+            }
+            if let Some(title) = title {
+                add_and!(str);
+                str += &format!("title '{}' -> '{}'", dom.title.unwrap_or_default(), title);
+            }
+            if description.is_some() {
+                add_and!(str);
+                str += "description changed";
+            }
+            if let Some(interstitial) = interstitial {
+                add_and!(str);
+                str += &format!("interstitial '{}' -> '{}'", dom.interstitial, interstitial);
+            }
+            if let Some(params) = params {
+                add_and!(str);
+                str += &format!("params '{}' -> '{}'", dom.params.unwrap_or_default(), params.unwrap_or_default());
+            }
+            if let Err(e) = crate::history::record(previous, &auth, &str).await {
+                println!("Could not record history of redirect: {:?}", e);
             }
             return Response::new(true, &format!("Edited redirect, {}", str)).json();
         }
         Ok(_) => Response::NOTHING_CHANGED().json(),
+        Err(e) if is_duplicate(&e) => Response::EXIST("Domain with the new name", "already").json(),
         Err(_) => Response::COULD_NOT("edit", "redirect").json()
     }
 }
 
-#[delete("/delete?<name>", data = "<user>")]
-async fn remove_redirect(name: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+#[openapi(tag = "Redirect")]
+#[delete("/delete?<name>&<namespace>", data = "<user>")]
+async fn remove_redirect(name: Option<String>, namespace: Option<String>, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
         Ok(a) => a,
         Err(e) => return e,
     };
     let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
-    let search_name = match get_search(auth, &name) {
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let search_name = match get_search(auth, &name, &namespace) {
         Ok(o) => o,
         Err(e) => return e
     };
     let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
-    let dom: Option<Domain> = ok_return!(db.find_one(search_name, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom: Option<Domain> = ok_return!(db.find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
     match dom {
+        // moved to trash, purged later or restored
         Some(dom) => {
-            let res = db.delete_one(doc! { "_id": dom._id }, None).await;
+            let res = db.update_one(doc! { "_id": dom._id }, doc! { "$set": { "deleted": DateTime::now() } }, None).await;
             match res {
-                Ok(r) if r.deleted_count > 0 => Response::new(true, &format!("Deleted redirect named '{}'", name)).json(),
+                Ok(r) if r.modified_count > 0 => Response::new(true, &format!("Deleted redirect named '{}'", name)).json(),
                 Ok(_) => Response::NOTHING_DELETED().json(),
                 Err(_) => Response::COULD_NOT("delete", "redirect").json()
             }
@@ -250,30 +509,34 @@ async fn remove_redirect(name: Option<String>, user: Json<PreAuth>) -> Json<Resp
 // AUTHS
 ////////////
 
-#[get("/", data = "<user>")]
-async fn list_auth(user: Json<PreAuth>) -> Json<Response> {
+#[openapi(tag = "Auth")]
+#[get("/?<query..>", data = "<user>")]
+async fn list_auth(query: AuthQuery, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
         Ok(a) => a,
         Err(e) => return e,
     };
     let document;
     if auth.permission.can_admin() {
-        document = None
+        document = doc! {}
     } else if auth.permission.can_manage() {
-        document = Some(doc! { "permission.0": {"$ne": 1}})
+        document = doc! { "permission.0": {"$ne": 1}}
     } else {
         return Response::PERMISSIONS_TOO_LOW().json();
     }
     let conn = connect().await.collection::<Auth>(AUTH_COLLECTION);
-    let cursor = ok_return!(conn.find(document, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
-    let collected: Vec<Auth> = ok_return!(cursor.try_collect().await, Response::DATABASE_WHILST_TRYING_TO_COLLECT().json());
-    let collected = ok_return!(serde_json::to_value(collected), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    let page = match query.page(&conn, query.filter(document)).await {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+    let page = ok_return!(serde_json::to_value(page), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
     Response {
         success: true,
-        response: collected,
+        response: page,
     }.json()
 }
 
+#[openapi(tag = "Auth")]
 #[post("/create?<name>&<password>&<permission>", data = "<user>")]
 async fn create_auth(name: Option<String>, password: Option<String>, permission: Option<u8>, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
@@ -299,6 +562,7 @@ async fn create_auth(name: Option<String>, password: Option<String>, permission:
                 name: name.clone(),
                 password: hashed,
                 permission,
+                params: None,
             }, None).await;
         match res {
             Ok(_) => Response::new(true, &format!("Created auth named '{}' with permission: {}.", name, permission)).json(),
@@ -309,8 +573,9 @@ async fn create_auth(name: Option<String>, password: Option<String>, permission:
     };
 }
 
-#[put("/edit?<name>&<newname>&<password>&<permission>", data = "<user>")]
-async fn edit_auth(name: Option<String>, newname: Option<String>, password: Option<String>, permission: Option<u8>, user: Json<PreAuth>) -> Json<Response> {
+#[openapi(tag = "Auth")]
+#[put("/edit?<name>&<newname>&<password>&<permission>&<params>", data = "<user>")]
+async fn edit_auth(name: Option<String>, newname: Option<String>, password: Option<String>, permission: Option<u8>, params: Option<String>, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
         Ok(a) => a,
         Err(e) => return e,
@@ -320,6 +585,10 @@ async fn edit_auth(name: Option<String>, newname: Option<String>, password: Opti
         None => None,
         Some(p) => Some(Permission::from_u8(p))
     };
+    let params = match check_params(params.as_deref(), &[]) {
+        Ok(p) => p,
+        Err(e) => return e.json(),
+    };
 
     return if auth.permission.can_admin() || (auth.permission.can_manage() && (permission.is_none() || !permission.clone().unwrap().can_manage())) {
         let db = connect().await.collection::<Auth>(AUTH_COLLECTION);
@@ -346,14 +615,15 @@ async fn edit_auth(name: Option<String>, newname: Option<String>, password: Opti
                     "$set": {
                         "name": newname.clone().unwrap_or(old_auth.name.clone()),
                         "password": hashed.clone().unwrap_or(old_auth.password.clone()),
-                        "permission": permission.unwrap_or(old_auth.permission)
+                        "permission": permission.unwrap_or(old_auth.permission),
+                        "params": params.clone().unwrap_or(old_auth.params.clone())
                     }
                 },
                 None)
             .await;
         match res {
             Ok(m) if m.modified_count > 0 => {
-                if newname.is_none() && password.is_none() && permission.is_none() {
+                if newname.is_none() && password.is_none() && permission.is_none() && params.is_none() {
                     return Response::NOTHING_CHANGED().json();
                 }
                 let mut str = "".to_string();
@@ -368,6 +638,10 @@ async fn edit_auth(name: Option<String>, newname: Option<String>, password: Opti
                     add_and!(str);
                     str += &format!("permission '{}' -> '{}'", old_auth.permission, permission);
                 }
+                if let Some(params) = params {
+                    add_and!(str);
+                    str += &format!("params '{}' -> '{}'", old_auth.params.unwrap_or_default(), params.unwrap_or_default());
+                }
                 return Response::new(true, &format!("Edited auth, {}", str)).json();
             }
             Ok(_) => Response::NOTHING_CHANGED().json(),
@@ -378,6 +652,7 @@ async fn edit_auth(name: Option<String>, newname: Option<String>, password: Opti
     };
 }
 
+#[openapi(tag = "Auth")]
 #[delete("/delete?<name>", data = "<user>")]
 async fn delete_auth(name: Option<String>, user: Json<PreAuth>) -> Json<Response> {
     let auth = match authorize(user).await {
@@ -436,6 +711,13 @@ impl Response {
         Json(self)
     }
 
+    fn message(self) -> String {
+        match self.response {
+            Value::String(s) => s,
+            v => v.to_string(),
+        }
+    }
+
     const DATABASE_WHILST_TRYING_TO_FIND: fn() -> Response = || Response::new(false, "Database error whilst trying to find.");
     const DATABASE_WHILST_TRYING_TO_COLLECT: fn() -> Response = || Response::new(false, "Database error whilst trying to collect data.");
     const SERVER_WHILST_TRYING_TO_FORMAT: fn() -> Response = || Response::new(false, "Server error whilst response formatting.");
@@ -445,17 +727,38 @@ impl Response {
     const COULD_NOT: fn(&str, &str) -> Response = |action: &str, thing: &str| Response::new(false, &format!("Could not {} {}.", action, thing));
     const NOTHING_CHANGED: fn() -> Response = || Response::new(false, "Nothing changed.");
     const NOTHING_DELETED: fn() -> Response = || Response::new(false, "Nothing deleted.");
-    const NOT_ALLOWED_DOMAIN_FORMAT: fn() -> Response = || Response::new(false, "Sent domain doesn't match the format e. g. https://example.com.");
+    const NOT_VALID_PARAM: fn(&str) -> Response = |param: &str| Response::new(false, &format!("User error, '{}' param is not valid.", param));
+    const NOT_ALLOWED_DOMAIN: fn(&str) -> Response = |reason: &str| Response::new(false, &format!("Sent domain is not allowed, {}.", reason));
+    const NOT_ALLOWED_NAME: fn(&str) -> Response = |reason: &str| Response::new(false, &format!("Sent name is not allowed, {}.", reason));
+    const IN_TRASH: fn() -> Response = || Response::new(false, "User error, redirect with that name is in trash, restore or purge it.");
+    const NOT_VALID_TARGET: fn(&str) -> Response = |what: &str| Response::new(false, &format!("User error, target has not valid {}.", what));
+    const NOT_FOUND: fn() -> Response = || Response::new(false, "Not found.");
+    const SERVER_ERROR: fn() -> Response = || Response::new(false, "Server error.");
 
     const USER_NOT_FOUND: fn() -> Response = || Response::new(false, "User not found.");
     const WRONG_PASSWORD: fn() -> Response = || Response::new(false, "Wrong password.");
     const BCRYPT_WHILST_TRYING_TO_VERIFY: fn() -> Response = || Response::new(false, "Bcrypt error whilst trying to verify user.");
 }
 
+//////////////
+// CATCHERS
+//////////////
+
+#[catch(404)]
+fn api_not_found() -> Json<Response> {
+    Response::NOT_FOUND().json()
+}
+
+#[catch(500)]
+fn api_server_error() -> Json<Response> {
+    Response::SERVER_ERROR().json()
+}
+
 //////////////////
 // INFO PATHS
 //////////////////
 
+#[openapi(skip)]
 #[get("/create")]
 fn i_create_post() -> Json<Response> {
     Json(Response {
@@ -464,6 +767,7 @@ fn i_create_post() -> Json<Response> {
     })
 }
 
+#[openapi(skip)]
 #[get("/edit")]
 fn i_edit_put() -> Json<Response> {
     Json(Response {
@@ -472,6 +776,7 @@ fn i_edit_put() -> Json<Response> {
     })
 }
 
+#[openapi(skip)]
 #[get("/delete")]
 fn i_delete_delete() -> Json<Response> {
     Json(Response {
@@ -480,6 +785,7 @@ fn i_delete_delete() -> Json<Response> {
     })
 }
 
+#[openapi(skip)]
 #[get("/random")]
 fn i_random_post() -> Json<Response> {
     Json(Response {
@@ -488,15 +794,121 @@ fn i_random_post() -> Json<Response> {
     })
 }
 
+#[openapi(skip)]
+#[get("/bulk")]
+fn i_bulk_post() -> Json<Response> {
+    Json(Response {
+        success: false,
+        response: Value::String("Use post".to_string()),
+    })
+}
+
+#[openapi(skip)]
+#[get("/import")]
+fn i_import_post() -> Json<Response> {
+    Json(Response {
+        success: false,
+        response: Value::String("Use post".to_string()),
+    })
+}
+
 //////////
 // OTHER
 //////////
 
-fn get_search(auth: Auth, name: &str) -> Result<Document, Json<Response>> {
+// redirects which auth can list, without trashed ones
+fn domain_scope(auth: &Auth) -> Result<Document, Json<Response>> {
+    if auth.permission.can_list() {
+        Ok(doc! { "deleted": null })
+    } else if auth.permission.can_own() {
+        Ok(doc! { "owner": auth._id, "deleted": null })
+    } else {
+        Err(Response::PERMISSIONS_TOO_LOW().json())
+    }
+}
+
+// rules every redirect destination has to follow, returns normalized destination
+fn check_domain_format(domain: &str, rules: &HostRules) -> Result<String, Response> {
+    let domain = validate_url(domain, &config::load::<UrlConfig>()).map_err(|e| Response::NOT_ALLOWED_DOMAIN(&e.to_string()))?;
+    rules.check(&domain).map_err(|e| Response::NOT_ALLOWED_DOMAIN(&e.to_string()))?;
+    Ok(domain)
+}
+
+// rules every redirect name has to follow
+fn check_name(name: &str) -> Result<(), Response> {
+    validate_name(name, &config::load::<NameConfig>()).map_err(|e| Response::NOT_ALLOWED_NAME(&e.to_string()))
+}
+
+// namespace has to be one of `short_hosts` from config, empty means global names
+fn check_namespace(namespace: Option<String>) -> Result<Option<String>, Response> {
+    match namespace.filter(|n| !n.is_empty()) {
+        None => Ok(None),
+        Some(n) => match config::load::<HostsConfig>().namespace(Some(&n)) {
+            Some(n) => Ok(Some(n)),
+            None => Err(Response::NOT_VALID_PARAM("namespace")),
+        },
+    }
+}
+
+fn check_text(param: &str, text: &Option<String>, max: usize) -> Result<(), Response> {
+    match text {
+        Some(t) if t.chars().count() > max => Err(Response::NOT_VALID_PARAM(param)),
+        _ => Ok(()),
+    }
+}
+
+// empty params remove them, none leaves them as they are
+fn check_params(params: Option<&str>, extra: &[(&str, &str)]) -> Result<Option<Option<String>>, Response> {
+    if params.is_none() && extra.is_empty() {
+        return Ok(None);
+    }
+    let built = build_params(params.unwrap_or_default(), extra).map_err(|_| Response::NOT_VALID_PARAM("params"))?;
+    if built.chars().count() > MAX_PARAMS_LENGTH {
+        return Err(Response::NOT_VALID_PARAM("params"));
+    }
+    Ok(Some(Some(built).filter(|p| !p.is_empty())))
+}
+
+// empty password removes protection, none leaves it as it is
+fn hash_access_password(password: Option<String>) -> Result<Option<Option<String>>, Response> {
+    match password {
+        None => Ok(None),
+        Some(p) if p.is_empty() => Ok(Some(None)),
+        Some(p) => match bcrypt::hash(p, bcrypt::DEFAULT_COST) {
+            Ok(h) => Ok(Some(Some(h))),
+            Err(_) => Err(Response::COULD_NOT("encrypt", "password")),
+        },
+    }
+}
+
+// lookups by name respecting `case_insensitive_names`
+fn name_options() -> FindOneOptions {
+    FindOneOptions::builder().collation(config::load::<NameConfig>().collation()).build()
+}
+
+// insert or update hit the unique index on names
+fn is_duplicate(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000)
+}
+
+// rejects loops and too long chains of redirects, returns destination to store
+async fn check_chain(name: &str, namespace: &Option<String>, domain: &str, links: &impl Links) -> Result<String, Response> {
+    let conf = config::load::<ChainConfig>();
+    let hosts = config::load::<HostsConfig>();
+    let last = resolve_chain(&LinkName::new(namespace, name), domain, &conf, &hosts, links).await.map_err(|e| Response::NOT_ALLOWED_DOMAIN(&e.to_string()))?;
+    Ok(if conf.flatten_chains { last } else { domain.to_string() })
+}
+
+// redirect using the name or keeping it as an alias
+fn name_taken(name: &str, namespace: &Option<String>) -> Document {
+    doc! { "$or": [{ "name": name }, { "aliases": name }], "namespace": namespace_bson(namespace) }
+}
+
+fn get_search(auth: Auth, name: &str, namespace: &Option<String>) -> Result<Document, Json<Response>> {
     if auth.permission.can_mod() {
-        Ok(doc! { "name": name.clone() })
+        Ok(doc! { "name": name.clone(), "namespace": namespace_bson(namespace), "deleted": null })
     } else if auth.permission.can_own() {
-        Ok(doc! { "name": name.clone(), "owner": auth._id })
+        Ok(doc! { "name": name.clone(), "namespace": namespace_bson(namespace), "owner": auth._id, "deleted": null })
     } else {
         Err(Response::PERMISSIONS_TOO_LOW().json())
     }
@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+use mongodb::{
+    Collection,
+    bson::{doc, DateTime, Document},
+    error::ErrorKind,
+    options::{FindOptions, InsertManyOptions},
+};
+use rocket::{
+    futures::TryStreamExt,
+    serde::json::Json,
+};
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use url::Url;
+use super::{PreAuth, Response, authorize, check_chain, check_domain_format};
+use super::listing::split_tags;
+use crate::{AUTH_COLLECTION, DOMAINS_COLLECTION, Domain, config, connect, import, ok_return, some_return};
+use crate::database::Auth;
+use crate::chains::{DbLinks, LinkName, Links, internal_name};
+use crate::host_rules::host_rules;
+use crate::redirect::HostsConfig;
+use crate::validation::{NameConfig, validate_name};
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct BulkConfig {
+    bulk_batch_size: usize,
+}
+
+impl Default for BulkConfig {
+    fn default() -> Self {
+        Self {
+            bulk_batch_size: 500,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(super) struct BulkCreate {
+    #[serde(flatten)]
+    user: PreAuth,
+    /// Rows of `name,domain[,tags]`, header row is optional
+    csv: Option<String>,
+    /// Array of redirects
+    redirects: Option<Vec<BulkRedirect>>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct BulkRedirect {
+    name: String,
+    domain: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(FromFormField, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ImportSource {
+    // SQL dump or JSON export
+    Yourls,
+    // CSV export
+    Bitly,
+    // CSV or JSON export
+    Shlink,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(super) struct Import {
+    #[serde(flatten)]
+    user: PreAuth,
+    /// Content of the exported file
+    data: String,
+}
+
+#[derive(Serialize, Default)]
+pub(super) struct BulkReport {
+    dry_run: bool,
+    created: usize,
+    conflicts: usize,
+    errors: usize,
+    rows: Vec<BulkRow>,
+}
+
+#[derive(Serialize)]
+struct BulkRow {
+    row: usize,
+    name: String,
+    status: RowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum RowStatus {
+    Created,
+    // would be created, used in dry run
+    Valid,
+    Conflict,
+    Error,
+}
+
+// row of the upload, either redirect ready to insert or the reason why it can't be created
+pub(super) struct Row {
+    pub(super) row: usize,
+    pub(super) name: String,
+    pub(super) domain: Result<Domain, String>,
+}
+
+#[openapi(tag = "Redirect")]
+#[post("/bulk?<dry_run>", data = "<bulk>")]
+pub(super) async fn bulk_redirect(dry_run: Option<bool>, bulk: Json<BulkCreate>) -> Json<Response> {
+    let bulk = bulk.into_inner();
+    let auth = match authorize(Json(bulk.user)).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_own() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let mut redirects = vec![];
+    if let Some(csv) = bulk.csv {
+        redirects = match parse_csv(&csv) {
+            Ok(r) => r,
+            Err(e) => return e.json(),
+        };
+    }
+    if let Some(json) = bulk.redirects {
+        redirects.extend(json);
+    }
+    if redirects.is_empty() {
+        return Response::USER_DID_NOT_PROVIDE_PARAM("csv' or 'redirects").json();
+    }
+    let host_rules = host_rules().await;
+    let rows = redirects
+        .into_iter()
+        .enumerate()
+        .map(|(i, r)| Row {
+            row: i + 1,
+            name: r.name.clone(),
+            domain: match check_domain_format(&r.domain, &host_rules) {
+                Ok(domain) => Ok(Domain {
+                    tags: r.tags,
+                    ..Domain::new(r.name, domain, auth._id)
+                }),
+                Err(e) => Err(e.message()),
+            },
+        })
+        .collect();
+    let report = match create_many(rows, dry_run.unwrap_or(false)).await {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+    let report = ok_return!(serde_json::to_value(report), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    Response {
+        success: true,
+        response: report,
+    }.json()
+}
+
+#[openapi(tag = "Redirect")]
+#[post("/import?<source>&<owner>&<dry_run>", data = "<import>")]
+pub(super) async fn import_redirects(source: Option<ImportSource>, owner: Option<String>, dry_run: Option<bool>, import: Json<Import>) -> Json<Response> {
+    let import = import.into_inner();
+    let auth = match authorize(Json(import.user)).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_own() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let source = some_return!(source, Response::USER_DID_NOT_PROVIDE_PARAM("source").json());
+    // imported redirects can be given to other auth
+    let owner = match owner {
+        Some(owner) if owner != auth.name => {
+            if !auth.permission.can_manage() {
+                return Response::PERMISSIONS_TOO_LOW().json();
+            }
+            let db = connect().await.collection::<Auth>(AUTH_COLLECTION);
+            let found = ok_return!(db.find_one(doc! { "name": owner }, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+            let found = some_return!(found, Response::EXIST("Auth", "doesn't").json());
+            if !auth.permission.can_admin() && found.permission.can_manage() {
+                return Response::PERMISSIONS_TOO_LOW().json();
+            }
+            found._id
+        }
+        _ => auth._id,
+    };
+    let links = match source {
+        ImportSource::Yourls => import::parse_yourls(&import.data),
+        ImportSource::Bitly => import::parse_bitly(&import.data),
+        ImportSource::Shlink => import::parse_shlink(&import.data),
+    };
+    let links = match links {
+        Ok(l) => l,
+        Err(e) => return Response::new(false, &e.to_string()).json(),
+    };
+    let host_rules = host_rules().await;
+    let rows = links
+        .into_iter()
+        .enumerate()
+        .map(|(i, l)| Row {
+            row: i + 1,
+            name: l.name.clone(),
+            domain: match check_domain_format(&l.domain, &host_rules) {
+                Ok(domain) => Ok(Domain {
+                    created: l.created.or(Some(DateTime::now())),
+                    clicks: l.clicks.unwrap_or(0),
+                    tags: l.tags,
+                    ..Domain::new(l.name, domain, owner)
+                }),
+                Err(e) => Err(e.message()),
+            },
+        })
+        .collect();
+    let report = match create_many(rows, dry_run.unwrap_or(false)).await {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+    let report = ok_return!(serde_json::to_value(report), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    Response {
+        success: true,
+        response: report,
+    }.json()
+}
+
+fn parse_csv(csv: &str) -> Result<Vec<BulkRedirect>, Response> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes());
+    let mut redirects = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = ok_return!(record, Err(Response::NOT_VALID_PARAM("csv")));
+        let name = record.get(0).unwrap_or_default();
+        // skip header
+        if i == 0 && name.eq_ignore_ascii_case("name") {
+            continue;
+        }
+        redirects.push(BulkRedirect {
+            name: name.to_string(),
+            domain: record.get(1).unwrap_or_default().to_string(),
+            tags: record.get(2).map(split_tags).unwrap_or_default(),
+        });
+    }
+    Ok(redirects)
+}
+
+// checks names against the database and inserts valid rows in batches
+pub(super) async fn create_many(rows: Vec<Row>, dry_run: bool) -> Result<BulkReport, Json<Response>> {
+    let conf = config::load::<BulkConfig>();
+    let names = config::load::<NameConfig>();
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let mut links = BatchLinks { links: DbLinks { col: &db, renamed: None }, names: &names, accepted: vec![] };
+    let mut report = BulkReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let batch: Vec<Row> = rows.by_ref().take(conf.bulk_batch_size.max(1)).collect();
+        let batch_names: Vec<&String> = batch.iter().map(|r| &r.name).collect();
+        let existing = existing_names(&db, &batch_names, &names).await?;
+        let mut to_insert = vec![];
+        let mut inserted_rows = vec![];
+        for row in batch {
+            let domain = row.domain.and_then(|d| match validate_name(&d.name, &names) {
+                Ok(_) => Ok(d),
+                Err(e) => Err(Response::NOT_ALLOWED_NAME(&e.to_string()).message()),
+            });
+            let key = names.key(&row.name);
+            let (status, message) = match domain {
+                Err(e) => (RowStatus::Error, Some(e)),
+                Ok(_) if existing.contains(&key) || !seen.insert(key) => {
+                    (RowStatus::Conflict, Some(Response::EXIST("Redirect", "already").message()))
+                }
+                Ok(mut domain) => match check_chain(&domain.name, &domain.namespace, &domain.domain, &links).await {
+                    Ok(destination) => {
+                        domain.domain = destination;
+                        links.accepted.push((LinkName::new(&domain.namespace, &domain.name), domain.domain.clone()));
+                        if !dry_run {
+                            to_insert.push(domain);
+                            inserted_rows.push(report.rows.len());
+                        }
+                        (if dry_run { RowStatus::Valid } else { RowStatus::Created }, None)
+                    }
+                    Err(e) => (RowStatus::Error, Some(e.message())),
+                },
+            };
+            report.rows.push(BulkRow {
+                row: row.row,
+                name: row.name,
+                status,
+                message,
+            });
+        }
+        if to_insert.is_empty() {
+            continue;
+        }
+        let options = InsertManyOptions::builder().ordered(false).build();
+        if let Err(e) = db.insert_many(to_insert, options).await {
+            println!("{:?}", e);
+            match *e.kind {
+                ErrorKind::BulkWrite(failure) => {
+                    for error in failure.write_errors.unwrap_or_default() {
+                        let row = &mut report.rows[inserted_rows[error.index]];
+                        if error.code == 11000 {
+                            row.status = RowStatus::Conflict;
+                            row.message = Some(Response::EXIST("Redirect", "already").message());
+                        } else {
+                            row.status = RowStatus::Error;
+                            row.message = Some(Response::COULD_NOT("create", "redirect").message());
+                        }
+                    }
+                }
+                _ => {
+                    for i in inserted_rows {
+                        report.rows[i].status = RowStatus::Error;
+                        report.rows[i].message = Some(Response::COULD_NOT("create", "redirect").message());
+                    }
+                }
+            }
+        }
+    }
+    for row in &report.rows {
+        match row.status {
+            RowStatus::Created | RowStatus::Valid => report.created += 1,
+            RowStatus::Conflict => report.conflicts += 1,
+            RowStatus::Error => report.errors += 1,
+        }
+    }
+    Ok(report)
+}
+
+// database links and rows accepted earlier in the same request, which aren't inserted yet
+struct BatchLinks<'a> {
+    links: DbLinks<'a>,
+    names: &'a NameConfig,
+    accepted: Vec<(LinkName, String)>,
+}
+
+impl BatchLinks<'_> {
+    fn same(&self, a: &LinkName, b: &LinkName) -> bool {
+        a.namespace == b.namespace && self.names.key(&a.name) == self.names.key(&b.name)
+    }
+}
+
+impl Links for BatchLinks<'_> {
+    async fn destination(&self, link: &LinkName) -> Option<String> {
+        if let Some(d) = self.links.destination(link).await {
+            return Some(d);
+        }
+        let global = LinkName { namespace: None, name: link.name.clone() };
+        self.accepted
+            .iter()
+            .find(|(n, _)| self.same(n, link))
+            .or_else(|| self.accepted.iter().find(|(n, _)| self.same(n, &global)))
+            .map(|(_, d)| d.clone())
+    }
+
+    async fn referrers(&self, link: &LinkName, base: &Url, root_links: bool, hosts: &HostsConfig) -> Vec<LinkName> {
+        let mut found = self.links.referrers(link, base, root_links, hosts).await;
+        found.extend(
+            self.accepted
+                .iter()
+                .filter(|(_, d)| internal_name(d, base, root_links, hosts).is_some_and(|n| self.same(&n, link)))
+                .map(|(n, _)| n.clone()),
+        );
+        found
+    }
+}
+
+async fn existing_names(db: &Collection<Domain>, names: &[&String], conf: &NameConfig) -> Result<HashSet<String>, Json<Response>> {
+    let options = FindOptions::builder().collation(conf.collation()).build();
+    let cursor = ok_return!(
+        db.clone_with_type::<Document>().find(doc! { "$or": [{ "name": { "$in": names } }, { "aliases": { "$in": names } }], "namespace": null }, options).await,
+        Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json())
+    );
+    let found: Vec<Document> = ok_return!(cursor.try_collect().await, Err(Response::DATABASE_WHILST_TRYING_TO_COLLECT().json()));
+    // aliases of found redirects can hold names of the batch too
+    Ok(found
+        .iter()
+        .flat_map(|d| {
+            let aliases = d.get_array("aliases").map(|a| a.iter().filter_map(|a| a.as_str()).collect()).unwrap_or_else(|_| vec![]);
+            d.get_str("name").into_iter().chain(aliases)
+        })
+        .map(|n| conf.key(n))
+        .collect())
+}
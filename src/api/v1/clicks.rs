@@ -0,0 +1,79 @@
+use mongodb::bson::{doc, Bson, Document};
+use rocket::{
+    futures::TryStreamExt,
+    serde::json::Json,
+};
+use rocket_okapi::openapi;
+use serde::Serialize;
+use super::{PreAuth, Response, authorize, check_namespace, domain_scope, name_options};
+use crate::{CLICKS_COLLECTION, DOMAINS_COLLECTION, Domain, connect, ok_return, some_return};
+use crate::redirect::namespace_bson;
+
+#[derive(Serialize)]
+struct ClickStats {
+    total: u64,
+    // clicks recorded by this instance, without totals kept from imports
+    recorded: u64,
+    variants: Vec<VariantClicks>,
+}
+
+#[derive(Serialize)]
+struct VariantClicks {
+    // none for clicks served without a variant
+    variant: Option<String>,
+    clicks: u64,
+}
+
+#[openapi(tag = "Redirect")]
+#[get("/clicks?<name>&<namespace>", data = "<user>")]
+pub(super) async fn click_stats(name: Option<String>, namespace: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let mut filter = match domain_scope(&auth) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+    filter.insert("name", &name);
+    filter.insert("namespace", namespace_bson(&namespace));
+    let db = connect().await;
+    let dom = ok_return!(db.collection::<Domain>(DOMAINS_COLLECTION).find_one(filter, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom = some_return!(dom, Response::EXIST("Redirect", "doesn't").json());
+    let pipeline = [
+        doc! { "$match": { "redirect": dom._id } },
+        doc! { "$group": { "_id": "$variant", "clicks": { "$sum": 1 } } },
+        doc! { "$sort": { "_id": 1 } },
+    ];
+    let cursor = ok_return!(db.collection::<Document>(CLICKS_COLLECTION).aggregate(pipeline, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let groups: Vec<Document> = ok_return!(cursor.try_collect().await, Response::DATABASE_WHILST_TRYING_TO_COLLECT().json());
+    let variants: Vec<VariantClicks> = groups
+        .into_iter()
+        .map(|g| VariantClicks {
+            variant: match g.get("_id") {
+                Some(Bson::String(v)) => Some(v.clone()),
+                _ => None,
+            },
+            clicks: match g.get("clicks") {
+                Some(Bson::Int32(c)) => *c as u64,
+                Some(Bson::Int64(c)) => *c as u64,
+                _ => 0,
+            },
+        })
+        .collect();
+    let stats = ClickStats {
+        total: dom.clicks,
+        recorded: variants.iter().map(|v| v.clicks).sum(),
+        variants,
+    };
+    let stats = ok_return!(serde_json::to_value(stats), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    Response {
+        success: true,
+        response: stats,
+    }.json()
+}
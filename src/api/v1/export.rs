@@ -0,0 +1,201 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use mongodb::options::FindOptions;
+use mongodb::bson::doc;
+use rocket::{
+    futures::{StreamExt, future, stream::{self, BoxStream}},
+    http::{ContentType, Header},
+    response::{self, Responder, stream::TextStream},
+    serde::json::Json,
+    Request,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::Responses,
+    openapi,
+    response::OpenApiResponderInner,
+    util::add_schema_response,
+};
+use schemars::JsonSchema;
+use super::{PreAuth, Response, authorize, domain_scope};
+use crate::{DOMAINS_COLLECTION, Domain, REDIRECT_PREFIX, connect, ok_return};
+
+#[derive(FromFormField, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ExportFormat {
+    Json,
+    Csv,
+    // nginx `map` file
+    Nginx,
+    // apache `RewriteMap` txt file
+    Apache,
+    // netlify `_redirects` file
+    Netlify,
+}
+
+pub(super) struct ExportFile {
+    inner: TextStream<BoxStream<'static, String>>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+#[openapi(tag = "Redirect")]
+#[get("/export?<format>", data = "<user>")]
+pub(super) async fn export_redirects(format: Option<ExportFormat>, user: Json<PreAuth>) -> Result<ExportFile, Json<Response>> {
+    let auth = authorize(user).await?;
+    let scope = domain_scope(&auth)?;
+    let format = format.unwrap_or(ExportFormat::Json);
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+    let cursor = ok_return!(db.find(scope, options).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
+    // documents are formatted as they come from the database, without collecting them first
+    // a database error ends the file with a marker instead of the footer, so it can't pass as complete
+    let failed = Arc::new(AtomicBool::new(false));
+    let failure = failed.clone();
+    let lines = cursor.scan(Some(0), move |next, d| {
+        let line = match (*next, d) {
+            (None, _) => None,
+            (Some(i), Ok(d)) => {
+                *next = Some(i + 1);
+                Some(format.line(i, &d.hide_password()))
+            }
+            (Some(_), Err(e)) => {
+                println!("Export failed: {:?}", e);
+                failure.store(true, Ordering::Relaxed);
+                *next = None;
+                Some(format.failure())
+            }
+        };
+        future::ready(line)
+    });
+    let footer = stream::once(async move { format.footer().filter(|_| !failed.load(Ordering::Relaxed)) }).filter_map(future::ready);
+    let stream = stream::iter(format.header())
+        .chain(lines)
+        .chain(footer)
+        .boxed();
+    Ok(ExportFile {
+        inner: TextStream(stream),
+        content_type: format.content_type(),
+        disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", format.file_name())),
+    })
+}
+
+impl ExportFormat {
+    fn content_type(self) -> ContentType {
+        match self {
+            ExportFormat::Json => ContentType::JSON,
+            ExportFormat::Csv => ContentType::CSV,
+            _ => ContentType::Plain,
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "redirects.json",
+            ExportFormat::Csv => "redirects.csv",
+            ExportFormat::Nginx => "redirects.map",
+            ExportFormat::Apache => "redirects.txt",
+            ExportFormat::Netlify => "_redirects",
+        }
+    }
+
+    fn header(self) -> Option<String> {
+        match self {
+            ExportFormat::Json => Some("[".to_string()),
+            ExportFormat::Csv => Some("name,domain,tags,created\n".to_string()),
+            ExportFormat::Nginx => Some("# use inside `map $uri $redirect { include redirects.map; }`\n".to_string()),
+            ExportFormat::Apache => Some(format!("# use with `RewriteMap redirects txt:redirects.txt` and `RewriteRule ^{}/(.+)$ ${{redirects:$1}} [R=303]`\n", REDIRECT_PREFIX)),
+            ExportFormat::Netlify => None,
+        }
+    }
+
+    fn footer(self) -> Option<String> {
+        match self {
+            ExportFormat::Json => Some("]".to_string()),
+            _ => None,
+        }
+    }
+
+    // last line of an incomplete file, breaks json and is a visible row or comment elsewhere
+    fn failure(self) -> String {
+        let message = "export failed with a database error, this file is incomplete";
+        match self {
+            ExportFormat::Json => format!("\n\"{}\"\n", message),
+            ExportFormat::Csv => format!("ERROR,{}\n", message),
+            _ => format!("# ERROR: {}\n", message),
+        }
+    }
+
+    fn line(self, i: usize, d: &Domain) -> String {
+        if let (ExportFormat::Nginx | ExportFormat::Apache | ExportFormat::Netlify, Some(reason)) = (self, unexportable(d)) {
+            // kept as a comment, a plain redirect would drop what the redirect depends on
+            return format!("# skipped '{}', {}\n", d.name.replace(['\r', '\n'], " "), reason);
+        }
+        match self {
+            ExportFormat::Json => {
+                let json = serde_json::to_string(d).unwrap_or_default();
+                if i == 0 { json } else { format!(",{}", json) }
+            }
+            ExportFormat::Csv => {
+                let created = d.created.and_then(|c| c.try_to_rfc3339_string().ok()).unwrap_or_default();
+                let mut writer = csv::Writer::from_writer(vec![]);
+                if let Err(e) = writer.write_record([&d.name, &d.domain, &d.tags.join(","), &created]) {
+                    println!("{:?}", e);
+                }
+                String::from_utf8(writer.into_inner().unwrap_or_default()).unwrap_or_default()
+            }
+            ExportFormat::Nginx => format!("\"{}/{}\" \"{}\";\n", REDIRECT_PREFIX, escape_quoted(&d.name), escape_quoted(&d.domain)),
+            ExportFormat::Apache => format!("{} {}\n", escape_spaces(&d.name), escape_spaces(&d.domain)),
+            ExportFormat::Netlify => format!("{}/{} {} 303\n", REDIRECT_PREFIX, escape_spaces(&d.name), escape_spaces(&d.domain)),
+        }
+    }
+}
+
+// why a redirect can't be written as a plain redirect of web server configs
+fn unexportable(d: &Domain) -> Option<&'static str> {
+    if d.access_password.is_some() {
+        Some("protected by password")
+    } else if d.namespace.is_some() {
+        Some("belongs to a short host")
+    } else if d.forward {
+        Some("forwards path and query")
+    } else if !d.targets.is_empty() || !d.variants.is_empty() {
+        Some("has targets or variants")
+    } else if !d.schedule.is_empty() || d.active_from.is_some() || d.active_until.is_some() {
+        Some("is scheduled")
+    } else if d.params.is_some() {
+        Some("adds query params")
+    } else if d.interstitial {
+        Some("shows a preview first")
+    } else {
+        None
+    }
+}
+
+fn escape_quoted(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_spaces(s: &str) -> String {
+    s.replace(' ', "%20")
+}
+
+impl<'r> Responder<'r, 'r> for ExportFile {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        response::Response::build_from(self.inner.respond_to(req)?)
+            .header(self.content_type)
+            .header(self.disposition)
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for ExportFile {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<String>();
+        add_schema_response(&mut responses, 200, "application/json", schema.clone())?;
+        add_schema_response(&mut responses, 200, "text/csv", schema.clone())?;
+        add_schema_response(&mut responses, 200, "text/plain", schema)?;
+        Ok(responses)
+    }
+}
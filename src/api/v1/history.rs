@@ -0,0 +1,115 @@
+use mongodb::{
+    bson::doc,
+    options::FindOptions,
+};
+use rocket::{
+    futures::TryStreamExt,
+    serde::json::Json,
+};
+use rocket_okapi::openapi;
+use super::{PreAuth, Response, authorize, check_chain, check_domain_format, check_name, check_namespace, get_search, is_duplicate, name_options, name_taken};
+use crate::{DOMAINS_COLLECTION, HISTORY_COLLECTION, Domain, connect, ok_return, some_return};
+use crate::chains::{DbLinks, LinkName};
+use crate::history::{Version, record, renamed_aliases, restore};
+use crate::host_rules::{HostRules, host_rules};
+
+#[openapi(tag = "Redirect")]
+#[get("/history?<name>&<namespace>", data = "<user>")]
+pub(super) async fn list_history(name: Option<String>, namespace: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let search_name = match get_search(auth, &name, &namespace) {
+        Ok(o) => o,
+        Err(e) => return e
+    };
+    let db = connect().await;
+    let dom = ok_return!(db.collection::<Domain>(DOMAINS_COLLECTION).find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom = some_return!(dom, Response::EXIST("Redirect", "doesn't").json());
+    let options = FindOptions::builder().sort(doc! { "version": -1 }).build();
+    let cursor = ok_return!(db.collection::<Version>(HISTORY_COLLECTION).find(doc! { "redirect": dom._id }, options).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let versions: Vec<Version> = ok_return!(cursor.try_collect().await, Response::DATABASE_WHILST_TRYING_TO_COLLECT().json());
+    // hashes of access passwords stay hidden in old versions too
+    let versions: Vec<Version> = versions.into_iter().map(|v| Version { state: v.state.hide_password(), ..v }).collect();
+    let versions = ok_return!(serde_json::to_value(versions), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    Response {
+        success: true,
+        response: versions,
+    }.json()
+}
+
+#[openapi(tag = "Redirect")]
+#[post("/rollback?<name>&<namespace>&<version>&<keep_alias>", data = "<user>")]
+pub(super) async fn rollback_redirect(name: Option<String>, namespace: Option<String>, version: Option<u32>, keep_alias: Option<bool>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
+    let version = some_return!(version, Response::USER_DID_NOT_PROVIDE_PARAM("version").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let search_name = match get_search(auth.clone(), &name, &namespace) {
+        Ok(o) => o,
+        Err(e) => return e
+    };
+    let db = connect().await;
+    let domains = db.collection::<Domain>(DOMAINS_COLLECTION);
+    let dom = ok_return!(domains.find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom = some_return!(dom, Response::EXIST("Redirect", "doesn't").json());
+    let old = ok_return!(db.collection::<Version>(HISTORY_COLLECTION).find_one(doc! { "redirect": dom._id, "version": version }, None).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let mut state = some_return!(old, Response::EXIST("Version", "doesn't").json()).state;
+    // the old name could be used by another redirect or not be allowed anymore since
+    if state.name != dom.name {
+        if let Err(e) = check_name(&state.name) {
+            return e.json();
+        }
+        let mut taken = name_taken(&state.name, &namespace);
+        taken.insert("_id", doc! { "$ne": dom._id });
+        let existing = ok_return!(domains.find_one(taken, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+        if existing.is_some() {
+            return Response::EXIST("Redirect with the old name", "already").json();
+        }
+    }
+    // rules for destinations and other redirects could have changed since
+    let rules = host_rules().await;
+    let renamed = Some(LinkName::new(&dom.namespace, &dom.name)).filter(|r| r.name != state.name);
+    let links = DbLinks { col: &domains, renamed };
+    let destinations = std::iter::once(&mut state.domain)
+        .chain(state.targets.iter_mut().map(|t| &mut t.domain))
+        .chain(state.variants.iter_mut().map(|v| &mut v.domain))
+        .chain(state.schedule.iter_mut().map(|s| &mut s.domain));
+    for domain in destinations {
+        *domain = match check_destination(&state.name, &dom.namespace, domain, &rules, &links).await {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+    }
+    state.aliases = renamed_aliases(&state.aliases, &dom.name, &state.name, keep_alias.unwrap_or(false));
+    match domains.update_one(doc! { "_id": dom._id }, doc! { "$set": restore(&state) }, None).await {
+        Ok(m) if m.modified_count > 0 => {
+            let change = format!("rolled back to version {}", version);
+            if let Err(e) = record(dom, &auth, &change).await {
+                println!("Could not record history of redirect: {:?}", e);
+            }
+            Response::new(true, &format!("Rolled back redirect '{}' to version {}.", state.name, version)).json()
+        }
+        Ok(_) => Response::NOTHING_CHANGED().json(),
+        Err(e) if is_duplicate(&e) => Response::EXIST("Redirect with the old name", "already").json(),
+        Err(_) => Response::COULD_NOT("edit", "redirect").json()
+    }
+}
+
+// destination of an old version, checked like a new one
+async fn check_destination(name: &str, namespace: &Option<String>, domain: &str, rules: &HostRules, links: &DbLinks<'_>) -> Result<String, Response> {
+    let domain = check_domain_format(domain, rules)?;
+    check_chain(name, namespace, &domain, links).await
+}
@@ -0,0 +1,77 @@
+use mongodb::bson::{doc, oid::ObjectId};
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use super::{PreAuth, Response, authorize};
+use crate::{HOST_RULES_COLLECTION, connect, ok_return, some_return};
+use crate::host_rules::{HostRule, MatchKind, RuleList, clear_cache, host_rules};
+
+#[openapi(tag = "Hosts")]
+#[get("/", data = "<user>")]
+pub(super) async fn list_hosts(user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_admin() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let rules = host_rules().await;
+    let rules = ok_return!(serde_json::to_value(rules.list()), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    Response {
+        success: true,
+        response: rules,
+    }.json()
+}
+
+#[openapi(tag = "Hosts")]
+#[post("/create?<list>&<kind>&<pattern>", data = "<user>")]
+pub(super) async fn create_host(list: Option<RuleList>, kind: Option<MatchKind>, pattern: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_admin() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let list = some_return!(list, Response::USER_DID_NOT_PROVIDE_PARAM("list").json());
+    let pattern = some_return!(pattern, Response::USER_DID_NOT_PROVIDE_PARAM("pattern").json());
+    let rule = HostRule {
+        _id: Some(ObjectId::new()),
+        list,
+        kind: kind.unwrap_or(MatchKind::Exact),
+        pattern,
+    };
+    let rule = match rule.normalized() {
+        Ok(r) => r,
+        Err(e) => return Response::new(false, &format!("User error, 'pattern' param is not valid: {}.", e)).json(),
+    };
+    let db = connect().await.collection::<HostRule>(HOST_RULES_COLLECTION);
+    let res = db.insert_one(&rule, None).await;
+    clear_cache();
+    match res {
+        Ok(_) => Response::new(true, &format!("Created {} rule for '{}' with id '{}'.", rule.list, rule.pattern, rule._id.unwrap_or_default())).json(),
+        Err(_) => Response::COULD_NOT("create", "host rule").json()
+    }
+}
+
+#[openapi(tag = "Hosts")]
+#[delete("/delete?<id>", data = "<user>")]
+pub(super) async fn delete_host(id: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_admin() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let id = some_return!(id, Response::USER_DID_NOT_PROVIDE_PARAM("id").json());
+    let id = ok_return!(ObjectId::parse_str(&id), Response::NOT_VALID_PARAM("id").json());
+    let db = connect().await.collection::<HostRule>(HOST_RULES_COLLECTION);
+    let res = db.delete_one(doc! { "_id": id }, None).await;
+    clear_cache();
+    match res {
+        Ok(r) if r.deleted_count > 0 => Response::new(true, &format!("Deleted host rule with id '{}'", id)).json(),
+        Ok(_) => Response::NOTHING_DELETED().json(),
+        Err(_) => Response::COULD_NOT("delete", "host rule").json()
+    }
+}
@@ -0,0 +1,248 @@
+use mongodb::{
+    Collection,
+    bson::{doc, Bson, DateTime, Document, oid::ObjectId},
+    options::FindOptions,
+};
+use rocket::{
+    futures::TryStreamExt,
+    serde::json::Json,
+};
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use super::Response;
+use crate::{config, ok_return};
+use crate::redirect::namespace_bson;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct ListConfig {
+    list_page_size: u32,
+    list_max_page_size: u32,
+}
+
+impl Default for ListConfig {
+    fn default() -> Self {
+        Self {
+            list_page_size: 50,
+            list_max_page_size: 500,
+        }
+    }
+}
+
+// query params for listing redirects
+// e.g. /api/v1/redirect?limit=20&sort=created&order=desc&host=example&tags=docs,blog
+#[derive(FromForm, JsonSchema, Default)]
+pub(crate) struct DomainQuery {
+    /// Max number of items on the page
+    limit: Option<u32>,
+    /// `_id` of the last item from previous page (returned as `next_cursor`)
+    cursor: Option<String>,
+    /// name | domain | created
+    sort: Option<String>,
+    /// asc | desc
+    order: Option<String>,
+    /// Text search in name and destination
+    search: Option<String>,
+    /// `_id` of the owner
+    owner: Option<String>,
+    /// Substring of the destination host
+    host: Option<String>,
+    /// Short host the redirects belong to, empty for global redirects
+    namespace: Option<String>,
+    /// RFC 3339 date e.g. 2022-08-20T00:00:00Z
+    created_after: Option<String>,
+    /// RFC 3339 date e.g. 2022-08-20T00:00:00Z
+    created_before: Option<String>,
+    /// Comma separated, redirect must have all of them
+    tags: Option<String>,
+    /// List redirects in trash instead
+    trash: Option<bool>,
+}
+
+// query params for listing auths
+#[derive(FromForm, JsonSchema, Default)]
+pub(crate) struct AuthQuery {
+    /// Max number of items on the page
+    limit: Option<u32>,
+    /// `_id` of the last item from previous page (returned as `next_cursor`)
+    cursor: Option<String>,
+    /// name
+    sort: Option<String>,
+    /// asc | desc
+    order: Option<String>,
+    /// Text search in name
+    search: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Page<T> {
+    items: Vec<T>,
+    total: u64,
+    next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub(crate) fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+struct PageOptions<'a> {
+    limit: Option<u32>,
+    cursor: &'a Option<String>,
+    sort: &'a Option<String>,
+    order: &'a Option<String>,
+}
+
+impl DomainQuery {
+    // adds filters from the query to the already scoped `filter`
+    pub(crate) fn filter(&self, mut filter: Document) -> Result<Document, Json<Response>> {
+        if self.trash == Some(true) {
+            filter.insert("deleted", doc! { "$ne": null });
+        }
+        let mut and: Vec<Document> = vec![];
+        if let Some(owner) = &self.owner {
+            let owner = ok_return!(ObjectId::parse_str(owner), Err(Response::NOT_VALID_PARAM("owner").json()));
+            and.push(doc! { "owner": owner });
+        }
+        if let Some(host) = &self.host {
+            let host = format!("^[^:]+://[^/?#]*{}", regex::escape(host));
+            and.push(doc! { "domain": { "$regex": host, "$options": "i" } });
+        }
+        if let Some(namespace) = &self.namespace {
+            let namespace = Some(namespace.to_lowercase()).filter(|n| !n.is_empty());
+            and.push(doc! { "namespace": namespace_bson(&namespace) });
+        }
+        if let Some(after) = &self.created_after {
+            let after = ok_return!(DateTime::parse_rfc3339_str(after), Err(Response::NOT_VALID_PARAM("created_after").json()));
+            and.push(doc! { "created": { "$gte": after } });
+        }
+        if let Some(before) = &self.created_before {
+            let before = ok_return!(DateTime::parse_rfc3339_str(before), Err(Response::NOT_VALID_PARAM("created_before").json()));
+            and.push(doc! { "created": { "$lte": before } });
+        }
+        if let Some(tags) = &self.tags {
+            and.push(doc! { "tags": { "$all": split_tags(tags) } });
+        }
+        if let Some(search) = &self.search {
+            let search = regex::escape(search);
+            and.push(doc! { "$or": [
+                { "name": { "$regex": search.clone(), "$options": "i" } },
+                { "domain": { "$regex": search, "$options": "i" } },
+            ] });
+        }
+        if !and.is_empty() {
+            and.insert(0, filter);
+            filter = doc! { "$and": and };
+        }
+        Ok(filter)
+    }
+
+    pub(crate) async fn page<T>(&self, col: &Collection<T>, filter: Document) -> Result<Page<T>, Json<Response>>
+        where T: DeserializeOwned + Unpin + Send + Sync {
+        let options = PageOptions {
+            limit: self.limit,
+            cursor: &self.cursor,
+            sort: &self.sort,
+            order: &self.order,
+        };
+        paginate(col, filter, options, &["name", "domain", "created"]).await
+    }
+}
+
+impl AuthQuery {
+    pub(crate) fn filter(&self, filter: Document) -> Document {
+        match &self.search {
+            Some(search) => doc! { "$and": [filter, { "name": { "$regex": regex::escape(search), "$options": "i" } }] },
+            None => filter
+        }
+    }
+
+    pub(crate) async fn page<T>(&self, col: &Collection<T>, filter: Document) -> Result<Page<T>, Json<Response>>
+        where T: DeserializeOwned + Unpin + Send + Sync {
+        let options = PageOptions {
+            limit: self.limit,
+            cursor: &self.cursor,
+            sort: &self.sort,
+            order: &self.order,
+        };
+        paginate(col, filter, options, &["name"]).await
+    }
+}
+
+// splits comma separated tags, skipping empty ones
+pub(crate) fn split_tags(tags: &str) -> Vec<String> {
+    tags
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+async fn paginate<T>(col: &Collection<T>, filter: Document, options: PageOptions<'_>, sortable: &[&str]) -> Result<Page<T>, Json<Response>>
+    where T: DeserializeOwned + Unpin + Send + Sync {
+    let conf = config::load::<ListConfig>();
+    let limit = options.limit.unwrap_or(conf.list_page_size).clamp(1, conf.list_max_page_size.max(1));
+    let sort = match options.sort {
+        None => "_id",
+        Some(s) if sortable.contains(&s.as_str()) => s.as_str(),
+        Some(_) => return Err(Response::NOT_VALID_PARAM("sort").json())
+    };
+    let (direction, compare) = match options.order.as_deref() {
+        None | Some("asc") => (1, "$gt"),
+        Some("desc") => (-1, "$lt"),
+        Some(_) => return Err(Response::NOT_VALID_PARAM("order").json())
+    };
+    let total = ok_return!(col.count_documents(filter.clone(), None).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
+    // cursor is the `_id` of the last item, its sort value is looked up to continue after it
+    let filter = match options.cursor {
+        None => filter,
+        Some(cursor) => {
+            let id = ok_return!(ObjectId::parse_str(cursor), Err(Response::NOT_VALID_PARAM("cursor").json()));
+            let after = if sort == "_id" {
+                doc! { "_id": { compare: id } }
+            } else {
+                let last = ok_return!(col.clone_with_type::<Document>().find_one(doc! { "_id": id }, None).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
+                let last = match last {
+                    Some(l) => l,
+                    None => return Err(Response::NOT_VALID_PARAM("cursor").json())
+                };
+                // missing values sort before all others, `$gt`/`$lt` never match them
+                let value = last.get(sort).cloned().unwrap_or(Bson::Null);
+                let same = doc! { sort: value.clone(), "_id": { compare: id } };
+                match (value, direction) {
+                    (Bson::Null, 1) => doc! { "$or": [{ sort: { "$ne": null } }, same] },
+                    (Bson::Null, _) => same,
+                    (value, 1) => doc! { "$or": [{ sort: { compare: value } }, same] },
+                    (value, _) => doc! { "$or": [{ sort: { compare: value } }, same, { sort: null }] },
+                }
+            };
+            doc! { "$and": [filter, after] }
+        }
+    };
+    let find_options = FindOptions::builder()
+        .sort(doc! { sort: direction, "_id": direction })
+        .limit(limit as i64 + 1)
+        .build();
+    let cursor = ok_return!(col.clone_with_type::<Document>().find(filter, find_options).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
+    let mut collected: Vec<Document> = ok_return!(cursor.try_collect().await, Err(Response::DATABASE_WHILST_TRYING_TO_COLLECT().json()));
+    let next_cursor = if collected.len() > limit as usize {
+        collected.truncate(limit as usize);
+        collected.last().and_then(|d| d.get_object_id("_id").ok()).map(|id| id.to_hex())
+    } else {
+        None
+    };
+    let mut items = Vec::with_capacity(collected.len());
+    for document in collected {
+        items.push(ok_return!(mongodb::bson::from_document(document), Err(Response::DATABASE_WHILST_TRYING_TO_COLLECT().json())));
+    }
+    Ok(Page {
+        items,
+        total,
+        next_cursor,
+    })
+}
@@ -0,0 +1,89 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use super::{PreAuth, Response, authorize, check_domain_format, check_namespace};
+use crate::{PATTERNS_COLLECTION, connect, ok_return, some_return};
+use crate::host_rules::host_rules;
+use crate::patterns::{PatternKind, PatternRule, clear_cache, pattern_rules};
+
+#[openapi(tag = "Patterns")]
+#[get("/", data = "<user>")]
+pub(super) async fn list_patterns(user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_list() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let rules = pattern_rules().await;
+    let rules = ok_return!(serde_json::to_value(rules.list()), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    Response {
+        success: true,
+        response: rules,
+    }.json()
+}
+
+#[openapi(tag = "Patterns")]
+#[post("/create?<kind>&<pattern>&<destination>&<priority>&<namespace>", data = "<user>")]
+pub(super) async fn create_pattern(kind: Option<PatternKind>, pattern: Option<String>, destination: Option<String>, priority: Option<i32>, namespace: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    // patterns can catch names of anyone
+    if !auth.permission.can_mod() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let pattern = some_return!(pattern, Response::USER_DID_NOT_PROVIDE_PARAM("pattern").json());
+    let destination = some_return!(destination, Response::USER_DID_NOT_PROVIDE_PARAM("destination").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let rule = PatternRule {
+        _id: ObjectId::new(),
+        kind: kind.unwrap_or(PatternKind::Wildcard),
+        pattern,
+        destination,
+        priority: priority.unwrap_or(0),
+        namespace,
+        owner: auth._id,
+        created: Some(DateTime::now()),
+    };
+    if let Err(e) = rule.compile() {
+        return Response::new(false, &format!("User error, 'pattern' param is not valid, {}.", e)).json();
+    }
+    if let Err(e) = check_domain_format(&rule.sample_destination(), &*host_rules().await) {
+        return e.json();
+    }
+    let db = connect().await.collection::<PatternRule>(PATTERNS_COLLECTION);
+    let res = db.insert_one(&rule, None).await;
+    clear_cache();
+    match res {
+        Ok(_) => Response::new(true, &format!("Created pattern '{}' redirecting to '{}' with id '{}'.", rule.pattern, rule.destination, rule._id)).json(),
+        Err(_) => Response::COULD_NOT("create", "pattern").json()
+    }
+}
+
+#[openapi(tag = "Patterns")]
+#[delete("/delete?<id>", data = "<user>")]
+pub(super) async fn delete_pattern(id: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let auth = match authorize(user).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    if !auth.permission.can_mod() {
+        return Response::PERMISSIONS_TOO_LOW().json();
+    }
+    let id = some_return!(id, Response::USER_DID_NOT_PROVIDE_PARAM("id").json());
+    let id = ok_return!(ObjectId::parse_str(&id), Response::NOT_VALID_PARAM("id").json());
+    let db = connect().await.collection::<PatternRule>(PATTERNS_COLLECTION);
+    let res = db.delete_one(doc! { "_id": id }, None).await;
+    clear_cache();
+    match res {
+        Ok(r) if r.deleted_count > 0 => Response::new(true, &format!("Deleted pattern with id '{}'", id)).json(),
+        Ok(_) => Response::NOTHING_DELETED().json(),
+        Err(_) => Response::COULD_NOT("delete", "pattern").json()
+    }
+}
@@ -0,0 +1,100 @@
+use mongodb::bson::doc;
+use rocket::{
+    http::ContentType,
+    response::{self, Responder},
+    serde::json::Json,
+    Request,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::openapi3::Responses,
+    openapi,
+    response::OpenApiResponderInner,
+    util::add_schema_response,
+};
+use schemars::JsonSchema;
+use super::{PreAuth, Response, authorize, check_namespace, domain_scope, name_options};
+use crate::{DOMAINS_COLLECTION, Domain, config, connect, ok_return, some_return};
+use crate::chains::ChainConfig;
+use crate::qr::{QrFormat, QrLevel, QrOptions, parse_color, png, svg};
+use crate::redirect::{namespace_bson, short_url};
+
+// query params for qr codes
+// e.g. /api/v1/redirect/qr?name=docs&format=svg&size=512&fg=224466
+#[derive(FromForm, JsonSchema)]
+pub(super) struct QrQuery {
+    name: Option<String>,
+    /// Short host the redirect belongs to, empty for global redirects
+    namespace: Option<String>,
+    /// png (default) | svg
+    format: Option<QrFormat>,
+    /// Width and height in pixels, 256 by default
+    size: Option<u32>,
+    /// Quiet zone around the code in modules, 4 by default
+    margin: Option<u32>,
+    /// Error correction l | m (default) | q | h
+    level: Option<QrLevel>,
+    /// Foreground color `rgb`, `rrggbb` or `rrggbbaa`, black by default
+    fg: Option<String>,
+    /// Background color, white by default
+    bg: Option<String>,
+}
+
+pub(super) struct QrImage {
+    body: Vec<u8>,
+    content_type: ContentType,
+}
+
+#[openapi(tag = "Redirect")]
+#[get("/qr?<query..>", data = "<user>")]
+pub(super) async fn qr_redirect(query: QrQuery, user: Json<PreAuth>) -> Result<QrImage, Json<Response>> {
+    let auth = authorize(user).await?;
+    let name = some_return!(query.name, Err(Response::USER_DID_NOT_PROVIDE_PARAM("name").json()));
+    let namespace = check_namespace(query.namespace).map_err(Response::json)?;
+    let defaults = QrOptions::default();
+    let options = QrOptions {
+        size: query.size.unwrap_or(defaults.size),
+        margin: query.margin.unwrap_or(defaults.margin),
+        level: query.level.unwrap_or(defaults.level),
+        foreground: match query.fg {
+            Some(c) => parse_color(&c).map_err(|_| Response::NOT_VALID_PARAM("fg").json())?,
+            None => defaults.foreground,
+        },
+        background: match query.bg {
+            Some(c) => parse_color(&c).map_err(|_| Response::NOT_VALID_PARAM("bg").json())?,
+            None => defaults.background,
+        },
+    };
+    // only redirects the user can see
+    let mut filter = domain_scope(&auth)?;
+    filter.insert("name", &name);
+    filter.insert("namespace", namespace_bson(&namespace));
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let dom = ok_return!(db.find_one(filter, name_options()).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
+    let dom = some_return!(dom, Err(Response::EXIST("Redirect", "doesn't").json()));
+    let conf = config::load::<ChainConfig>();
+    let url = short_url(&conf.public_url, conf.root_links, &dom.name, dom.namespace.as_deref());
+    let image = match query.format.unwrap_or(QrFormat::Png) {
+        QrFormat::Png => png(&url, &options).map(|body| QrImage { body, content_type: ContentType::PNG }),
+        QrFormat::Svg => svg(&url, &options).map(|body| QrImage { body: body.into_bytes(), content_type: ContentType::SVG }),
+    };
+    image.map_err(|e| Response::COULD_NOT("create", &format!("qr code, {}", e)).json())
+}
+
+impl<'r> Responder<'r, 'static> for QrImage {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        response::Response::build_from(self.body.respond_to(req)?)
+            .header(self.content_type)
+            .ok()
+    }
+}
+
+impl OpenApiResponderInner for QrImage {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<String>();
+        add_schema_response(&mut responses, 200, "image/png", schema.clone())?;
+        add_schema_response(&mut responses, 200, "image/svg+xml", schema)?;
+        Ok(responses)
+    }
+}
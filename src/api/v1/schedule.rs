@@ -0,0 +1,114 @@
+use mongodb::bson::{doc, to_bson};
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use super::{PreAuth, Response, authorize, check_chain, check_domain_format, check_namespace, get_search, name_options};
+use crate::{DOMAINS_COLLECTION, Domain, connect, ok_return, some_return};
+use crate::chains::DbLinks;
+use crate::host_rules::host_rules;
+use crate::schedule::{Scheduled, parse_time};
+
+// longer schedules are rejected
+const MAX_SCHEDULED: usize = 64;
+
+#[derive(Deserialize, JsonSchema)]
+pub(super) struct Schedule {
+    #[serde(flatten)]
+    user: PreAuth,
+    /// Wall clock time in `schedule_timezone` like `2026-11-02T09:00`, missing means active already
+    active_from: Option<String>,
+    /// Redirect is a miss from this time on, missing means active forever
+    active_until: Option<String>,
+    /// Destinations replacing the default one from their start, empty array removes them
+    #[serde(default)]
+    schedule: Vec<ScheduledEntry>,
+    /// Schedule starts over this many days after its first entry
+    repeat_days: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(super) struct ScheduledEntry {
+    start: String,
+    domain: String,
+}
+
+#[openapi(tag = "Redirect")]
+#[put("/schedule?<name>&<namespace>", data = "<body>")]
+pub(super) async fn set_schedule(name: Option<String>, namespace: Option<String>, body: Json<Schedule>) -> Json<Response> {
+    let body = body.into_inner();
+    let auth = match authorize(Json(body.user)).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let active_from = match body.active_from.as_deref().map(parse_time) {
+        Some(None) => return Response::NOT_VALID_PARAM("active_from").json(),
+        t => t.flatten(),
+    };
+    let active_until = match body.active_until.as_deref().map(parse_time) {
+        Some(None) => return Response::NOT_VALID_PARAM("active_until").json(),
+        t => t.flatten(),
+    };
+    if let (Some(from), Some(until)) = (active_from, active_until) {
+        if from >= until {
+            return Response::NOT_VALID_PARAM("active_until").json();
+        }
+    }
+    if body.schedule.len() > MAX_SCHEDULED || body.repeat_days == Some(0) || (body.repeat_days.is_some() && body.schedule.is_empty()) {
+        return Response::NOT_VALID_PARAM("schedule").json();
+    }
+    let search_name = match get_search(auth.clone(), &name, &namespace) {
+        Ok(o) => o,
+        Err(e) => return e
+    };
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let dom: Option<Domain> = ok_return!(db.find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom = some_return!(dom, Response::EXIST("Redirect", "doesn't").json());
+    let rules = host_rules().await;
+    let links = DbLinks { col: &db, renamed: None };
+    let mut schedule = Vec::with_capacity(body.schedule.len());
+    for entry in body.schedule {
+        let start = some_return!(parse_time(&entry.start), Response::NOT_VALID_PARAM("schedule").json());
+        let domain = match check_domain_format(&entry.domain, &rules) {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+        let domain = match check_chain(&dom.name, &dom.namespace, &domain, &links).await {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+        schedule.push(Scheduled { start, domain });
+    }
+    schedule.sort_by_key(|s| s.start);
+    // every entry has to fit in one repetition
+    if let (Some(days), Some(first), Some(last)) = (body.repeat_days, schedule.first(), schedule.last()) {
+        if (last.start - first.start).num_days() >= i64::from(days) {
+            return Response::NOT_VALID_PARAM("repeat_days").json();
+        }
+    }
+    let bson = ok_return!(to_bson(&schedule), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    let update = doc! {
+        "$set": {
+            "active_from": ok_return!(to_bson(&active_from), Response::SERVER_WHILST_TRYING_TO_FORMAT().json()),
+            "active_until": ok_return!(to_bson(&active_until), Response::SERVER_WHILST_TRYING_TO_FORMAT().json()),
+            "schedule": bson,
+            "schedule_repeat_days": body.repeat_days,
+        }
+    };
+    match db.update_one(doc! { "_id": dom._id }, update, None).await {
+        Ok(m) if m.modified_count > 0 => {
+            let message = format!("Set schedule of redirect '{}' with {} destinations.", dom.name, schedule.len());
+            if let Err(e) = crate::history::record(dom, &auth, &format!("schedule set to {} destinations", schedule.len())).await {
+                println!("Could not record history of redirect: {:?}", e);
+            }
+            Response::new(true, &message).json()
+        }
+        Ok(_) => Response::NOTHING_CHANGED().json(),
+        Err(_) => Response::COULD_NOT("edit", "redirect").json()
+    }
+}
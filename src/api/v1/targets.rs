@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use mongodb::bson::{doc, to_bson};
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use super::{PreAuth, Response, authorize, check_chain, check_domain_format, check_namespace, get_search, name_options};
+use crate::{DOMAINS_COLLECTION, Domain, connect, ok_return, some_return};
+use crate::chains::DbLinks;
+use crate::host_rules::host_rules;
+use crate::targeting::{Target, Variant, total_weight};
+
+// longer lists of targets and variants are rejected
+const MAX_TARGETS: usize = 32;
+const MAX_VARIANTS: usize = 16;
+const MAX_VARIANT_NAME_LENGTH: usize = 32;
+
+#[derive(Deserialize, JsonSchema)]
+pub(super) struct Targets {
+    #[serde(flatten)]
+    user: PreAuth,
+    /// Tried in order, empty array removes targets
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(super) struct Variants {
+    #[serde(flatten)]
+    user: PreAuth,
+    /// Traffic is split by weight, empty array removes variants
+    variants: Vec<Variant>,
+}
+
+#[openapi(tag = "Redirect")]
+#[put("/targets?<name>&<namespace>", data = "<body>")]
+pub(super) async fn set_targets(name: Option<String>, namespace: Option<String>, body: Json<Targets>) -> Json<Response> {
+    let body = body.into_inner();
+    let auth = match authorize(Json(body.user)).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    if body.targets.len() > MAX_TARGETS {
+        return Response::NOT_VALID_PARAM("targets").json();
+    }
+    let search_name = match get_search(auth.clone(), &name, &namespace) {
+        Ok(o) => o,
+        Err(e) => return e
+    };
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let dom: Option<Domain> = ok_return!(db.find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom = some_return!(dom, Response::EXIST("Redirect", "doesn't").json());
+    // every destination is checked like the default one
+    let rules = host_rules().await;
+    let links = DbLinks { col: &db, renamed: None };
+    let mut targets = Vec::with_capacity(body.targets.len());
+    for target in body.targets {
+        let target = match target.normalized() {
+            Ok(t) => t,
+            Err(e) => return Response::NOT_VALID_TARGET(&e).json(),
+        };
+        let domain = match check_domain_format(&target.domain, &rules) {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+        let domain = match check_chain(&dom.name, &dom.namespace, &domain, &links).await {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+        targets.push(Target { domain, ..target });
+    }
+    let bson = ok_return!(to_bson(&targets), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    let res = db.update_one(doc! { "_id": dom._id }, doc! { "$set": { "targets": bson } }, None).await;
+    match res {
+        Ok(m) if m.modified_count > 0 => {
+            let message = format!("Set {} targets of redirect '{}'.", targets.len(), dom.name);
+            if let Err(e) = crate::history::record(dom, &auth, &format!("targets set to {}", targets.len())).await {
+                println!("Could not record history of redirect: {:?}", e);
+            }
+            Response::new(true, &message).json()
+        }
+        Ok(_) => Response::NOTHING_CHANGED().json(),
+        Err(_) => Response::COULD_NOT("edit", "redirect").json()
+    }
+}
+
+#[openapi(tag = "Redirect")]
+#[put("/variants?<name>&<namespace>&<sticky>", data = "<body>")]
+pub(super) async fn set_variants(name: Option<String>, namespace: Option<String>, sticky: Option<bool>, body: Json<Variants>) -> Json<Response> {
+    let body = body.into_inner();
+    let auth = match authorize(Json(body.user)).await {
+        Ok(a) => a,
+        Err(e) => return e,
+    };
+    let name = some_return!(name, Response::USER_DID_NOT_PROVIDE_PARAM("name").json());
+    let namespace = match check_namespace(namespace) {
+        Ok(n) => n,
+        Err(e) => return e.json(),
+    };
+    let mut names = HashSet::new();
+    let named = body.variants.iter().all(|v| {
+        let label = v.name.trim();
+        !label.is_empty() && label.chars().count() <= MAX_VARIANT_NAME_LENGTH && names.insert(label.to_string())
+    });
+    if body.variants.len() > MAX_VARIANTS || !named || (!body.variants.is_empty() && total_weight(&body.variants) == 0) {
+        return Response::NOT_VALID_PARAM("variants").json();
+    }
+    let search_name = match get_search(auth.clone(), &name, &namespace) {
+        Ok(o) => o,
+        Err(e) => return e
+    };
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let dom: Option<Domain> = ok_return!(db.find_one(search_name, name_options()).await, Response::DATABASE_WHILST_TRYING_TO_FIND().json());
+    let dom = some_return!(dom, Response::EXIST("Redirect", "doesn't").json());
+    let rules = host_rules().await;
+    let links = DbLinks { col: &db, renamed: None };
+    let mut variants = Vec::with_capacity(body.variants.len());
+    for variant in body.variants {
+        let domain = match check_domain_format(&variant.domain, &rules) {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+        let domain = match check_chain(&dom.name, &dom.namespace, &domain, &links).await {
+            Ok(d) => d,
+            Err(e) => return e.json(),
+        };
+        variants.push(Variant { name: variant.name.trim().to_string(), domain, ..variant });
+    }
+    let bson = ok_return!(to_bson(&variants), Response::SERVER_WHILST_TRYING_TO_FORMAT().json());
+    let sticky = sticky.unwrap_or(dom.sticky);
+    let res = db.update_one(doc! { "_id": dom._id }, doc! { "$set": { "variants": bson, "sticky": sticky } }, None).await;
+    match res {
+        Ok(m) if m.modified_count > 0 => {
+            let message = format!("Set {} variants of redirect '{}'.", variants.len(), dom.name);
+            if let Err(e) = crate::history::record(dom, &auth, &format!("variants set to {}", variants.len())).await {
+                println!("Could not record history of redirect: {:?}", e);
+            }
+            Response::new(true, &message).json()
+        }
+        Ok(_) => Response::NOTHING_CHANGED().json(),
+        Err(_) => Response::COULD_NOT("edit", "redirect").json()
+    }
+}
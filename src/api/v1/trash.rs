@@ -0,0 +1,47 @@
+use mongodb::bson::doc;
+use rocket::serde::json::Json;
+use rocket_okapi::openapi;
+use super::{PreAuth, Response, authorize, check_namespace, get_search, name_options};
+use crate::{DOMAINS_COLLECTION, Domain, connect, ok_return, some_return};
+use crate::database::purge;
+
+// redirect in trash the user can manage
+async fn find_trashed(name: Option<String>, namespace: Option<String>, user: Json<PreAuth>) -> Result<Domain, Json<Response>> {
+    let auth = authorize(user).await?;
+    let name = some_return!(name, Err(Response::USER_DID_NOT_PROVIDE_PARAM("name").json()));
+    let namespace = check_namespace(namespace).map_err(Response::json)?;
+    let mut search = get_search(auth, &name, &namespace)?;
+    search.insert("deleted", doc! { "$ne": null });
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    let dom = ok_return!(db.find_one(search, name_options()).await, Err(Response::DATABASE_WHILST_TRYING_TO_FIND().json()));
+    dom.ok_or_else(|| Response::EXIST("Redirect in trash", "doesn't").json())
+}
+
+#[openapi(tag = "Redirect")]
+#[post("/restore?<name>&<namespace>", data = "<user>")]
+pub(super) async fn restore_redirect(name: Option<String>, namespace: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let dom = match find_trashed(name, namespace, user).await {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+    let db = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+    match db.update_one(doc! { "_id": dom._id }, doc! { "$unset": { "deleted": "" } }, None).await {
+        Ok(m) if m.modified_count > 0 => Response::new(true, &format!("Restored redirect named '{}'", dom.name)).json(),
+        Ok(_) => Response::NOTHING_CHANGED().json(),
+        Err(_) => Response::COULD_NOT("restore", "redirect").json()
+    }
+}
+
+#[openapi(tag = "Redirect")]
+#[delete("/purge?<name>&<namespace>", data = "<user>")]
+pub(super) async fn purge_redirect(name: Option<String>, namespace: Option<String>, user: Json<PreAuth>) -> Json<Response> {
+    let dom = match find_trashed(name, namespace, user).await {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+    match purge(&[dom._id]).await {
+        Ok(n) if n > 0 => Response::new(true, &format!("Purged redirect named '{}'", dom.name)).json(),
+        Ok(_) => Response::NOTHING_DELETED().json(),
+        Err(_) => Response::COULD_NOT("delete", "redirect").json()
+    }
+}
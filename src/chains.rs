@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Collection;
+use mongodb::options::FindOneOptions;
+use rocket::futures::TryStreamExt;
+use rocket::http::RawStr;
+use serde::Deserialize;
+use url::Url;
+use crate::{DOMAIN, Domain, REDIRECT_PREFIX};
+use crate::redirect::{HostsConfig, namespace_bson};
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct ChainConfig {
+    // url this instance is reachable at, destinations under it point to other redirects
+    pub(crate) public_url: String,
+    // how many redirects of this instance can follow each other
+    pub(crate) max_chain_depth: usize,
+    // store final destination instead of a link to another redirect
+    pub(crate) flatten_chains: bool,
+    // same key as for mounting redirects at `/<name>`
+    pub(crate) root_links: bool,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            public_url: DOMAIN.to_string(),
+            max_chain_depth: 3,
+            flatten_chains: false,
+            root_links: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum ChainError {
+    SelfReference,
+    Loop(Vec<String>),
+    TooLong(usize),
+}
+
+impl Display for ChainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainError::SelfReference => write!(f, "redirect would point to itself"),
+            ChainError::Loop(chain) => write!(f, "redirects would form a loop ({})", chain.join(" -> ")),
+            ChainError::TooLong(max) => write!(f, "chain of redirects would be longer than {}", max),
+        }
+    }
+}
+
+// redirect a destination points to, global names have no namespace
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct LinkName {
+    pub(crate) namespace: Option<String>,
+    pub(crate) name: String,
+}
+
+impl LinkName {
+    pub(crate) fn new(namespace: &Option<String>, name: &str) -> Self {
+        Self { namespace: namespace.clone(), name: name.to_string() }
+    }
+}
+
+impl Display for LinkName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.namespace {
+            Some(n) => write!(f, "{}/{}", n, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+// redirects of this instance, database or anything else in tests
+pub(crate) trait Links {
+    // destination of redirect, none when it doesn't exist
+    async fn destination(&self, link: &LinkName) -> Option<String>;
+    // redirects pointing to redirect `link`
+    async fn referrers(&self, link: &LinkName, base: &Url, root_links: bool, hosts: &HostsConfig) -> Vec<LinkName>;
+}
+
+pub(crate) struct DbLinks<'a> {
+    pub(crate) col: &'a Collection<Domain>,
+    // old name of redirect being renamed, its record doesn't count anymore
+    pub(crate) renamed: Option<LinkName>,
+}
+
+impl DbLinks<'_> {
+    // leaves out the record of the renamed redirect
+    fn filter(&self, mut filter: Document) -> Document {
+        if let Some(r) = &self.renamed {
+            filter.insert("$nor", vec![doc! { "name": &r.name, "namespace": namespace_bson(&r.namespace) }]);
+        }
+        filter
+    }
+}
+
+impl Links for DbLinks<'_> {
+    async fn destination(&self, link: &LinkName) -> Option<String> {
+        // short hosts find their own names first, then global ones
+        let filter = self.filter(doc! {
+            "$or": [{ "name": &link.name }, { "aliases": &link.name }],
+            "namespace": { "$in": [namespace_bson(&link.namespace), Bson::Null] },
+            "deleted": null,
+        });
+        let options = FindOneOptions::builder().sort(doc! { "namespace": -1 }).build();
+        match self.col.find_one(filter, options).await {
+            Ok(d) => d.map(|d| d.domain),
+            Err(e) => {
+                println!("{:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn referrers(&self, link: &LinkName, base: &Url, root_links: bool, hosts: &HostsConfig) -> Vec<LinkName> {
+        // global names are reachable through the public url and every short host
+        let mut hosts: Vec<String> = match &link.namespace {
+            Some(n) => vec![regex::escape(n)],
+            None => hosts.short_hosts.keys().map(|h| regex::escape(&h.to_lowercase())).collect(),
+        };
+        if link.namespace.is_none() {
+            let port = base.port().map(|p| format!(":{}", p)).unwrap_or_default();
+            hosts.push(format!("{}{}", regex::escape(base.host_str().unwrap_or_default()), regex::escape(&port)));
+        }
+        let pattern = format!(
+            "^https?://({}){}({}){}/{}([/?#]|$)",
+            hosts.join("|"),
+            regex::escape(base.path().trim_end_matches('/')),
+            regex::escape(REDIRECT_PREFIX),
+            if root_links { "?" } else { "" },
+            regex::escape(&link.name)
+        );
+        let filter = self.filter(doc! { "domain": { "$regex": pattern }, "deleted": null });
+        let cursor = match self.col.find(filter, None).await {
+            Ok(c) => c,
+            Err(e) => {
+                println!("{:?}", e);
+                return vec![];
+            }
+        };
+        match cursor.try_collect::<Vec<Domain>>().await {
+            Ok(found) => found.into_iter().map(|d| LinkName { namespace: d.namespace, name: d.name }).collect(),
+            Err(e) => {
+                println!("{:?}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+// redirect of this instance the destination points to
+// links to the public url point to global names, links to short hosts to names of their namespace
+pub(crate) fn internal_name(destination: &str, base: &Url, root_links: bool, hosts: &HostsConfig) -> Option<LinkName> {
+    let url = Url::parse(destination).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let namespace = hosts.namespace(url.host_str());
+    if namespace.is_none() && (url.host_str() != base.host_str() || url.port() != base.port()) {
+        return None;
+    }
+    let root = format!("{}/", base.path().trim_end_matches('/'));
+    let prefix = format!("{}{}/", base.path().trim_end_matches('/'), REDIRECT_PREFIX);
+    let path = match url.path().strip_prefix(&prefix) {
+        Some(p) => p,
+        None if root_links => url.path().strip_prefix(&root)?,
+        None => return None,
+    };
+    let name = path.split('/').next()?;
+    if name.is_empty() {
+        return None;
+    }
+    let name = RawStr::new(name).percent_decode().ok()?;
+    Some(LinkName { namespace, name: name.to_string() })
+}
+
+// follows destinations pointing back to this instance, returns final destination
+// links to other hosts can't close a loop, so only those are checked
+pub(crate) async fn resolve_chain(link: &LinkName, destination: &str, conf: &ChainConfig, hosts: &HostsConfig, links: &impl Links) -> Result<String, ChainError> {
+    let base = match Url::parse(&conf.public_url) {
+        Ok(b) => b,
+        Err(e) => {
+            println!("Public url '{}' is not valid: {:?}", conf.public_url, e);
+            return Ok(destination.to_string());
+        }
+    };
+    let mut chain = vec![link.clone()];
+    let mut destination = destination.to_string();
+    while let Some(next) = internal_name(&destination, &base, conf.root_links, hosts) {
+        if next == *link && chain.len() == 1 {
+            return Err(ChainError::SelfReference);
+        }
+        let looped = chain.contains(&next);
+        chain.push(next.clone());
+        if looped {
+            return Err(ChainError::Loop(chain.iter().map(LinkName::to_string).collect()));
+        }
+        if chain.len() - 1 > conf.max_chain_depth {
+            return Err(ChainError::TooLong(conf.max_chain_depth));
+        }
+        match links.destination(&next).await {
+            Some(d) => destination = d,
+            None => break,
+        }
+    }
+    // redirects pointing to this one make the chain longer too
+    if chain.len() > 1 {
+        let mut seen: HashSet<LinkName> = chain.iter().cloned().collect();
+        let mut level = vec![link.clone()];
+        let mut depth = chain.len() - 1;
+        while !level.is_empty() {
+            let mut next_level = vec![];
+            for n in &level {
+                for r in links.referrers(n, &base, conf.root_links, hosts).await {
+                    if seen.insert(r.clone()) {
+                        next_level.push(r);
+                    }
+                }
+            }
+            if next_level.is_empty() {
+                break;
+            }
+            depth += 1;
+            if depth > conf.max_chain_depth {
+                return Err(ChainError::TooLong(conf.max_chain_depth));
+            }
+            level = next_level;
+        }
+    }
+    Ok(destination)
+}
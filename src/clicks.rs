@@ -0,0 +1,43 @@
+use mongodb::bson::{doc, DateTime, oid::ObjectId};
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::{CLICKS_COLLECTION, DOMAINS_COLLECTION, Domain, connect};
+use crate::api::openapi::{DateTimeSchema, ObjectIdSchema};
+
+// one served redirect
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub(crate) struct Click {
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) _id: ObjectId,
+    // `_id` of the redirect
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) redirect: ObjectId,
+    // variant served by split redirects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) variant: Option<String>,
+    #[schemars(with = "DateTimeSchema")]
+    pub(crate) time: DateTime,
+}
+
+// stores the click without delaying the redirect
+pub(crate) fn record_click(redirect: ObjectId, variant: Option<String>) {
+    rocket::tokio::spawn(async move {
+        let db = connect().await;
+        let click = Click {
+            _id: ObjectId::new(),
+            redirect,
+            variant,
+            time: DateTime::now(),
+        };
+        if let Err(e) = db.collection::<Click>(CLICKS_COLLECTION).insert_one(click, None).await {
+            println!("{:?}", e);
+        }
+        let total = db
+            .collection::<Domain>(DOMAINS_COLLECTION)
+            .update_one(doc! { "_id": redirect }, doc! { "$inc": { "clicks": 1_i64 } }, None)
+            .await;
+        if let Err(e) = total {
+            println!("{:?}", e);
+        }
+    });
+}
@@ -0,0 +1,28 @@
+use rocket::Config;
+use serde::de::DeserializeOwned;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+// extracted settings by type, Rocket.toml is read once per settings struct
+static LOADED: LazyLock<Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// reads settings from Rocket.toml (or ROCKET_ env variables)
+// every settings struct should use `#[serde(default)]` so missing keys fall back to defaults
+pub(crate) fn load<T: DeserializeOwned + Default + Clone + Send + Sync + 'static>() -> T {
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    loaded
+        .entry(TypeId::of::<T>())
+        .or_insert_with(|| {
+            Box::new(match Config::figment().extract::<T>() {
+                Ok(conf) => conf,
+                Err(e) => {
+                    println!("Could not read config: {:?}. Using default values", e);
+                    T::default()
+                }
+            })
+        })
+        .downcast_ref::<T>()
+        .cloned()
+        .unwrap_or_default()
+}
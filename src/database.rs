@@ -1,29 +1,226 @@
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 use std::{env, process};
-use mongodb::{Client, Database};
-use mongodb::bson::Bson;
+use chrono::NaiveDateTime;
+use mongodb::{Client, Database, IndexModel};
+use mongodb::bson::{doc, Bson, DateTime, Document};
 use mongodb::bson::oid::ObjectId;
 use mongodb::error::ErrorKind;
-use mongodb::options::ClientOptions;
+use mongodb::options::{ClientOptions, FindOptions, IndexOptions};
 use rocket::Config;
+use rocket::futures::TryStreamExt;
 use rocket::tokio::join;
+use rocket::tokio::time::interval;
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
-use crate::{add_and, AUTH_COLLECTION, DATABASE_NAME, DOMAINS_COLLECTION};
+use crate::{add_and, config, AUTH_COLLECTION, CLICKS_COLLECTION, COUNTERS_COLLECTION, PATTERNS_COLLECTION, DATABASE_NAME, DOMAINS_COLLECTION, HISTORY_COLLECTION, HOST_RULES_COLLECTION};
+use crate::access::HIDDEN_PASSWORD;
+use crate::clicks::Click;
+use crate::history::Version;
+use crate::schedule::Scheduled;
+use crate::targeting::{Target, Variant};
+use crate::api::openapi::{DateTimeSchema, ObjectIdSchema};
+use crate::validation::NameConfig;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
 pub(crate) struct Domain {
+    #[schemars(with = "ObjectIdSchema")]
     pub(crate) _id: ObjectId,
     pub(crate) name: String,
     pub(crate) domain: String,
+    #[schemars(with = "ObjectIdSchema")]
     pub(crate) owner: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<DateTimeSchema>")]
+    pub(crate) created: Option<DateTime>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+    // total of clicks, kept from other shorteners when imported
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub(crate) clicks: u64,
+    // remaining path and query of `/r/<name>/<rest..>` are added to destination
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) forward: bool,
+    // short host the name belongs to, none for global names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) namespace: Option<String>,
+    // bcrypt hash of password asked before redirecting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) access_password: Option<String>,
+    // shown on the preview page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+    // preview with countdown is shown before every redirect
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) interstitial: bool,
+    // tried in order before `domain`, which is the default
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) targets: Vec<Target>,
+    // traffic is split between these by weight when no target matches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) variants: Vec<Variant>,
+    // visitors keep their variant through a cookie
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub(crate) sticky: bool,
+    // query like `utm_source=news` added to destination, keys it already has are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) params: Option<String>,
+    // wall clock times in `schedule_timezone`, the redirect is a miss outside of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) active_from: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) active_until: Option<NaiveDateTime>,
+    // replaces `domain` from the start of each entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) schedule: Vec<Scheduled>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) schedule_repeat_days: Option<u32>,
+    // moved to trash at, trashed redirects keep their name until purged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<DateTimeSchema>")]
+    pub(crate) deleted: Option<DateTime>,
+    // previous names still redirecting here, reserved like names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) aliases: Vec<String>,
+    // name and aliases, the unique index on it keeps names and aliases of redirects apart
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) names: Vec<String>,
+    // default params of the owner, only filled when redirecting and never stored
+    #[serde(default, skip_serializing)]
+    #[schemars(skip)]
+    pub(crate) owner_params: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl Domain {
+    // copy for api users, hash of access password is replaced
+    pub(crate) fn hide_password(mut self) -> Self {
+        if self.access_password.is_some() {
+            self.access_password = Some(HIDDEN_PASSWORD.to_string());
+        }
+        self
+    }
+
+    pub(crate) fn new(name: String, domain: String, owner: ObjectId) -> Self {
+        Self {
+            _id: ObjectId::new(),
+            names: all_names(&name, &[]),
+            owner_params: None,
+            name,
+            domain,
+            owner,
+            created: Some(DateTime::now()),
+            tags: vec![],
+            clicks: 0,
+            forward: false,
+            namespace: None,
+            access_password: None,
+            title: None,
+            description: None,
+            interstitial: false,
+            targets: vec![],
+            variants: vec![],
+            sticky: false,
+            params: None,
+            active_from: None,
+            active_until: None,
+            schedule: vec![],
+            schedule_repeat_days: None,
+            deleted: None,
+            aliases: vec![],
+        }
+    }
+}
+
+// value of `names` for a redirect
+pub(crate) fn all_names(name: &str, aliases: &[String]) -> Vec<String> {
+    std::iter::once(name).chain(aliases.iter().map(String::as_str)).map(String::from).collect()
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
 pub(crate) struct Auth {
+    #[schemars(with = "ObjectIdSchema")]
     pub(crate) _id: ObjectId,
     pub(crate) name: String,
     pub(crate) password: String,
     pub(crate) permission: Permission,
+    // added to destinations of owned redirects after their own params
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) params: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct TrashConfig {
+    // trashed redirects are purged after this many days, 0 keeps them
+    trash_retention_days: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            trash_retention_days: 30,
+        }
+    }
+}
+
+// how often trash is checked for redirects past retention
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// removes redirects for good, clicks and versions have nothing to belong to anymore
+pub(crate) async fn purge(ids: &[ObjectId]) -> mongodb::error::Result<u64> {
+    let db = connect().await;
+    let deleted = db.collection::<Domain>(DOMAINS_COLLECTION).delete_many(doc! { "_id": { "$in": ids } }, None).await?;
+    for col in [CLICKS_COLLECTION, HISTORY_COLLECTION] {
+        if let Err(e) = db.collection::<Document>(col).delete_many(doc! { "redirect": { "$in": ids } }, None).await {
+            println!("{:?}", e);
+        }
+    }
+    Ok(deleted.deleted_count)
+}
+
+// purges redirects in trash longer than `trash_retention_days`, runs until shutdown
+pub(crate) async fn purge_trash() {
+    let retention = config::load::<TrashConfig>().trash_retention_days;
+    if retention == 0 {
+        return;
+    }
+    let col = connect().await.collection::<Document>(DOMAINS_COLLECTION);
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+    let mut interval = interval(TRASH_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let before = DateTime::from_millis(DateTime::now().timestamp_millis() - (retention * 24 * 60 * 60 * 1000) as i64);
+        let expired = match col.find(doc! { "deleted": { "$lte": before } }, options.clone()).await {
+            Ok(cursor) => cursor.try_collect::<Vec<Document>>().await,
+            Err(e) => Err(e),
+        };
+        let ids: Vec<ObjectId> = match expired {
+            Ok(found) => found.iter().filter_map(|d| d.get_object_id("_id").ok()).collect(),
+            Err(e) => {
+                println!("Could not find expired trash: {:?}", e);
+                continue;
+            }
+        };
+        if ids.is_empty() {
+            continue;
+        }
+        match purge(&ids).await {
+            Ok(n) => println!("Purged {} redirects from trash", n),
+            Err(e) => println!("Could not purge trash: {:?}", e),
+        }
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -119,7 +316,86 @@ pub(crate) async fn manage_database() {
 
     let create_domains = create_collection_unless(&db, DOMAINS_COLLECTION, 3);
     let create_auths = create_collection_unless(&db, AUTH_COLLECTION, 3);
-    join!(create_domains, create_auths);
+    let create_host_rules = create_collection_unless(&db, HOST_RULES_COLLECTION, 3);
+    let create_counters = create_collection_unless(&db, COUNTERS_COLLECTION, 3);
+    let create_patterns = create_collection_unless(&db, PATTERNS_COLLECTION, 3);
+    let create_clicks = create_collection_unless(&db, CLICKS_COLLECTION, 3);
+    let create_history = create_collection_unless(&db, HISTORY_COLLECTION, 3);
+    join!(create_domains, create_auths, create_host_rules, create_counters, create_patterns, create_clicks, create_history);
+
+    // clicks are counted per redirect and variant
+    let clicks = db.collection::<Click>(CLICKS_COLLECTION);
+    let index = IndexModel::builder().keys(doc! { "redirect": 1, "variant": 1 }).build();
+    if let Err(e) = clicks.create_index(index, None).await {
+        println!("Could not create index on clicks: {:?}", e);
+    }
+
+    // redirects from before `created` was stored get the time of their `_id`
+    let legacy = db
+        .collection::<Domain>(DOMAINS_COLLECTION)
+        .update_many(doc! { "created": { "$exists": false } }, vec![doc! { "$set": { "created": { "$toDate": "$_id" } } }], None)
+        .await;
+    if let Err(e) = legacy {
+        println!("Could not set creation time of old redirects: {:?}", e);
+    }
+
+    // versions are listed per redirect, newest first, concurrent edits can't take the same number
+    let history = db.collection::<Version>(HISTORY_COLLECTION);
+    let index = IndexModel::builder()
+        .keys(doc! { "redirect": 1, "version": -1 })
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    if let Err(e) = history.create_index(index, None).await {
+        println!("Could not create index on history: {:?}", e);
+    }
+
+    // unique names in every namespace even when created at the same time
+    let names = config::load::<NameConfig>();
+    let domains = db.collection::<Domain>(DOMAINS_COLLECTION);
+    let index = IndexModel::builder()
+        .keys(doc! { "namespace": 1, "name": 1 })
+        .options(IndexOptions::builder().name("namespace_name_unique".to_string()).unique(true).collation(names.collation()).build())
+        .build();
+    match domains.create_index(index, None).await {
+        Ok(_) => println!("Created unique index on redirect names"),
+        Err(e) => match *e.kind {
+            ErrorKind::Command(c) if c.code == 85 || c.code == 86 => {
+                println!("Index on redirect names differs from config (case_insensitive_names), drop index 'namespace_name_unique' to recreate it")
+            }
+            ErrorKind::Command(c) if c.code == 11000 => {
+                println!("Could not create unique index, redirect names are not unique: {}", c.message)
+            }
+            e => println!("Could not create unique index on redirect names: {:?}", e),
+        },
+    }
+
+    // names and aliases can't be used twice in a namespace, redirects from before aliases get their names
+    let legacy = domains
+        .update_many(
+            doc! { "names": { "$exists": false } },
+            vec![doc! { "$set": { "names": { "$concatArrays": [["$name"], { "$ifNull": ["$aliases", []] }] } } }],
+            None,
+        )
+        .await;
+    if let Err(e) = legacy {
+        println!("Could not set names of old redirects: {:?}", e);
+    }
+    let index = IndexModel::builder()
+        .keys(doc! { "namespace": 1, "names": 1 })
+        .options(IndexOptions::builder().name("namespace_names_unique".to_string()).unique(true).collation(names.collation()).build())
+        .build();
+    match domains.create_index(index, None).await {
+        Ok(_) => {}
+        Err(e) => match *e.kind {
+            ErrorKind::Command(c) if c.code == 85 || c.code == 86 => {
+                println!("Index on redirect names differs from config (case_insensitive_names), drop index 'namespace_names_unique' to recreate it")
+            }
+            ErrorKind::Command(c) if c.code == 11000 => {
+                println!("Could not create unique index, redirect names and aliases are not unique: {}", c.message)
+            }
+            e => println!("Could not create unique index on redirect names and aliases: {:?}", e),
+        },
+    }
 
     // add default auth if not found any
     let a_col = db.collection::<Auth>(AUTH_COLLECTION);
@@ -134,6 +410,7 @@ pub(crate) async fn manage_database() {
                 name: "admin".to_string(),
                 password: h,
                 permission: Permission(1, 0, 0, 0, 0, 0),
+                params: None,
             }, None).await;
             match res {
                 Ok(_) => println!("No auth found, created new auth"),
@@ -151,7 +428,7 @@ pub(crate) async fn manage_database() {
 // 4 - create/edit/delete/list own redirects
 // 5 - create random named redirects
 
-#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, Copy)]
 pub(crate) struct Permission(u8, u8, u8, u8, u8, u8);
 
 impl Permission {
@@ -0,0 +1,89 @@
+use mongodb::bson::{doc, to_bson, DateTime, Document, oid::ObjectId};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::FindOneOptions;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::{HISTORY_COLLECTION, Domain, connect};
+use crate::api::openapi::{DateTimeSchema, ObjectIdSchema};
+use crate::database::{Auth, all_names};
+
+// redirect as it was before one edit
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub(crate) struct Version {
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) _id: ObjectId,
+    // `_id` of the redirect
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) redirect: ObjectId,
+    // 1 for the redirect as created, rollbacks refer to it
+    pub(crate) version: u32,
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) editor: ObjectId,
+    pub(crate) editor_name: String,
+    #[schemars(with = "DateTimeSchema")]
+    pub(crate) time: DateTime,
+    // what the edit changed, as in its response
+    pub(crate) change: String,
+    pub(crate) state: Domain,
+}
+
+// concurrent edits taking the same version number try again with the next one
+const RECORD_TRIES: usize = 5;
+
+// stores the state before an edit as the next version of the redirect
+pub(crate) async fn record(previous: Domain, editor: &Auth, change: &str) -> mongodb::error::Result<()> {
+    let col = connect().await.collection::<Version>(HISTORY_COLLECTION);
+    let options = FindOneOptions::builder().sort(doc! { "version": -1 }).build();
+    let mut tries = 0;
+    loop {
+        let last = col.find_one(doc! { "redirect": previous._id }, options.clone()).await?;
+        let version = Version {
+            _id: ObjectId::new(),
+            redirect: previous._id,
+            version: last.map(|v| v.version + 1).unwrap_or(1),
+            editor: editor._id,
+            editor_name: editor.name.clone(),
+            time: DateTime::now(),
+            change: change.to_string(),
+            state: previous.clone(),
+        };
+        tries += 1;
+        match col.insert_one(version, None).await {
+            Err(e) if tries < RECORD_TRIES && matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(w)) if w.code == 11000) => continue,
+            res => return res.map(|_| ()),
+        }
+    }
+}
+
+// `$set` bringing back what edits, targets, variants and schedule can change
+pub(crate) fn restore(state: &Domain) -> Document {
+    doc! {
+        "name": &state.name,
+        "domain": &state.domain,
+        "tags": &state.tags,
+        "forward": state.forward,
+        "access_password": &state.access_password,
+        "title": &state.title,
+        "description": &state.description,
+        "interstitial": state.interstitial,
+        "params": &state.params,
+        "aliases": to_bson(&state.aliases).unwrap_or_default(),
+        "names": to_bson(&all_names(&state.name, &state.aliases)).unwrap_or_default(),
+        "targets": to_bson(&state.targets).unwrap_or_default(),
+        "variants": to_bson(&state.variants).unwrap_or_default(),
+        "sticky": state.sticky,
+        "active_from": to_bson(&state.active_from).unwrap_or_default(),
+        "active_until": to_bson(&state.active_until).unwrap_or_default(),
+        "schedule": to_bson(&state.schedule).unwrap_or_default(),
+        "schedule_repeat_days": state.schedule_repeat_days,
+    }
+}
+
+// aliases after renaming, the new name stops being one and the old one is added when kept
+pub(crate) fn renamed_aliases(aliases: &[String], name: &str, newname: &str, keep: bool) -> Vec<String> {
+    let mut aliases: Vec<String> = aliases.iter().filter(|a| *a != newname).cloned().collect();
+    if keep && name != newname && !aliases.iter().any(|a| a == name) {
+        aliases.push(name.to_string());
+    }
+    aliases
+}
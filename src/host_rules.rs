@@ -0,0 +1,265 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+use mongodb::bson::oid::ObjectId;
+use regex::{Regex, RegexBuilder};
+use rocket::futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use url::{Host, Url};
+use crate::{HOST_RULES_COLLECTION, config, connect};
+use crate::api::openapi::ObjectIdSchema;
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+struct HostRulesConfig {
+    // patterns like `example.com`, `suffix:example.com` or `regex:^phish`
+    blocked_hosts: Vec<String>,
+    // when not empty, only matching hosts can be used
+    allowed_hosts: Vec<String>,
+    // file with lines like `block suffix:example.com` or `allow example.org`, `#` starts a comment
+    host_rules_file: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, FromFormField, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RuleList {
+    Block,
+    Allow,
+}
+
+impl Display for RuleList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleList::Block => write!(f, "block"),
+            RuleList::Allow => write!(f, "allow"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, FromFormField, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MatchKind {
+    // host is the same as pattern
+    Exact,
+    // host is the pattern or its subdomain
+    Suffix,
+    // host matches regex
+    Regex,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub(crate) struct HostRule {
+    // only rules from the database have id, others come from config or file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<ObjectIdSchema>")]
+    pub(crate) _id: Option<ObjectId>,
+    pub(crate) list: RuleList,
+    pub(crate) kind: MatchKind,
+    pub(crate) pattern: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum HostError {
+    NoHost,
+    Blocked(String),
+    NotAllowed(String),
+    // rules from the database couldn't be read
+    Unavailable,
+}
+
+impl Display for HostError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostError::NoHost => write!(f, "url has no host"),
+            HostError::Blocked(h) => write!(f, "host '{}' is blocked", h),
+            HostError::NotAllowed(h) => write!(f, "host '{}' is not on the allowlist", h),
+            HostError::Unavailable => write!(f, "host rules could not be loaded, try again later"),
+        }
+    }
+}
+
+pub(crate) struct HostRules {
+    rules: Vec<(HostRule, Option<Regex>)>,
+    // some rules are missing, every host is rejected
+    unavailable: bool,
+}
+
+// rules are cached until they change through the api
+static CACHE: RwLock<Option<Arc<HostRules>>> = RwLock::new(None);
+
+// all rules from config, file and database
+pub(crate) async fn host_rules() -> Arc<HostRules> {
+    if let Some(rules) = CACHE.read().ok().and_then(|c| c.clone()) {
+        return rules;
+    }
+    let mut rules = config_rules();
+    let col = connect().await.collection::<HostRule>(HOST_RULES_COLLECTION);
+    let found = match col.find(None, None).await {
+        Ok(cursor) => cursor.try_collect::<Vec<HostRule>>().await,
+        Err(e) => Err(e),
+    };
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => {
+            // not cached, next check tries the database again
+            println!("Could not read host rules: {:?}", e);
+            return Arc::new(HostRules { unavailable: true, ..HostRules::new(rules) });
+        }
+    };
+    rules.extend(found);
+    let rules = Arc::new(HostRules::new(rules));
+    if let Ok(mut cache) = CACHE.write() {
+        *cache = Some(rules.clone());
+    }
+    rules
+}
+
+pub(crate) fn clear_cache() {
+    if let Ok(mut cache) = CACHE.write() {
+        *cache = None;
+    }
+}
+
+fn config_rules() -> Vec<HostRule> {
+    let conf = config::load::<HostRulesConfig>();
+    let mut rules: Vec<HostRule> = conf.blocked_hosts
+        .iter()
+        .map(|p| HostRule::parse(RuleList::Block, p))
+        .chain(conf.allowed_hosts.iter().map(|p| HostRule::parse(RuleList::Allow, p)))
+        .collect();
+    if let Some(path) = conf.host_rules_file {
+        match std::fs::read_to_string(&path) {
+            Ok(file) => rules.extend(parse_rules_file(&file)),
+            Err(e) => println!("Could not read host rules file '{}': {:?}", path, e),
+        }
+    }
+    rules
+}
+
+pub(crate) fn parse_rules_file(file: &str) -> Vec<HostRule> {
+    file
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or_default().trim())
+        .filter(|l| !l.is_empty())
+        .filter_map(|l| {
+            let (list, pattern) = l.split_once(char::is_whitespace)?;
+            let list = match list {
+                "block" => RuleList::Block,
+                "allow" => RuleList::Allow,
+                _ => {
+                    println!("Unknown host rule list '{}', use `block` or `allow`", list);
+                    return None;
+                }
+            };
+            Some(HostRule::parse(list, pattern.trim()))
+        })
+        .collect()
+}
+
+impl HostRule {
+    // `suffix:example.com`, `regex:^phish` or `example.com`
+    pub(crate) fn parse(list: RuleList, pattern: &str) -> Self {
+        let (kind, pattern) = match pattern.split_once(':') {
+            Some(("suffix", p)) => (MatchKind::Suffix, p),
+            Some(("regex", p)) => (MatchKind::Regex, p),
+            _ => (MatchKind::Exact, pattern),
+        };
+        HostRule {
+            _id: None,
+            list,
+            kind,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    // checks pattern and normalizes hosts to how they are stored in destinations (lowercase, punycode)
+    pub(crate) fn normalized(mut self) -> Result<Self, String> {
+        match self.kind {
+            MatchKind::Regex => {
+                compile(&self.pattern)?;
+            }
+            MatchKind::Exact | MatchKind::Suffix => {
+                let trimmed = self.pattern.trim();
+                let pattern = trimmed.trim_start_matches("*.").trim_start_matches('.');
+                // `*.example.com` and `.example.com` are meant for subdomains too
+                if pattern.len() != trimmed.len() {
+                    self.kind = MatchKind::Suffix;
+                }
+                self.pattern = match Host::parse(pattern) {
+                    Ok(h) => h.to_string(),
+                    Err(e) => return Err(e.to_string()),
+                };
+            }
+        }
+        Ok(self)
+    }
+
+    fn matches(&self, regex: &Option<Regex>, host: &str) -> bool {
+        match self.kind {
+            MatchKind::Exact => host == self.pattern,
+            MatchKind::Suffix => host == self.pattern || host.ends_with(&format!(".{}", self.pattern)),
+            MatchKind::Regex => regex.as_ref().map(|r| r.is_match(host)).unwrap_or(false),
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(1 << 16)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+impl HostRules {
+    pub(crate) fn new(rules: Vec<HostRule>) -> Self {
+        let rules = rules
+            .into_iter()
+            .filter_map(|r| match r.normalized() {
+                Ok(r) => {
+                    let regex = match r.kind {
+                        MatchKind::Regex => compile(&r.pattern).ok(),
+                        _ => None,
+                    };
+                    Some((r, regex))
+                }
+                Err(e) => {
+                    println!("Skipping host rule: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules, unavailable: false }
+    }
+
+    pub(crate) fn list(&self) -> Vec<&HostRule> {
+        self.rules.iter().map(|(r, _)| r).collect()
+    }
+
+    // checks host of the destination url
+    pub(crate) fn check(&self, url: &str) -> Result<(), HostError> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.trim_end_matches('.').to_lowercase()))
+            .ok_or(HostError::NoHost)?;
+        if self.unavailable {
+            return Err(HostError::Unavailable);
+        }
+        let mut has_allow = false;
+        let mut allowed = false;
+        for (rule, regex) in &self.rules {
+            match rule.list {
+                RuleList::Block if rule.matches(regex, &host) => return Err(HostError::Blocked(host)),
+                RuleList::Block => {}
+                RuleList::Allow => {
+                    has_allow = true;
+                    allowed = allowed || rule.matches(regex, &host);
+                }
+            }
+        }
+        if has_allow && !allowed {
+            return Err(HostError::NotAllowed(host));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt::{Display, Formatter};
+use mongodb::bson::DateTime;
+use serde_json::Value;
+
+// link read from other shortener's export
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ImportedLink {
+    pub(crate) name: String,
+    pub(crate) domain: String,
+    pub(crate) created: Option<DateTime>,
+    pub(crate) clicks: Option<u64>,
+    pub(crate) tags: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum ImportError {
+    NotCsv,
+    NotJson,
+    // export doesn't have one of the needed columns or fields
+    MissingColumn(&'static str),
+    NothingFound,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::NotCsv => write!(f, "Could not read CSV."),
+            ImportError::NotJson => write!(f, "Could not read JSON."),
+            ImportError::MissingColumn(c) => write!(f, "Export is missing '{}' column.", c),
+            ImportError::NothingFound => write!(f, "No links found in export."),
+        }
+    }
+}
+
+////////////
+// YOURLS
+////////////
+
+// SQL dump (`INSERT INTO yourls_url ...`) or JSON from `action=stats` api / plugin exports
+pub(crate) fn parse_yourls(data: &str) -> Result<Vec<ImportedLink>, ImportError> {
+    if data.to_ascii_lowercase().contains("insert into") {
+        return parse_yourls_sql(data);
+    }
+    let json: Value = serde_json::from_str(data).map_err(|_| ImportError::NotJson)?;
+    // api response has `links` object with `link_1`, `link_2`... keys, exports are plain arrays
+    let links: Vec<&Value> = match &json {
+        Value::Array(a) => a.iter().collect(),
+        Value::Object(o) => match o.get("links") {
+            Some(Value::Object(l)) => l.values().collect(),
+            Some(Value::Array(l)) => l.iter().collect(),
+            _ => return Err(ImportError::NothingFound),
+        },
+        _ => return Err(ImportError::NothingFound),
+    };
+    let mut imported = vec![];
+    for link in links {
+        let name = match json_str(link, &["keyword"]) {
+            Some(k) => k,
+            None => match json_str(link, &["shorturl"]) {
+                Some(s) => last_segment(&s),
+                None => return Err(ImportError::MissingColumn("keyword")),
+            },
+        };
+        imported.push(ImportedLink {
+            name,
+            domain: json_str(link, &["url"]).ok_or(ImportError::MissingColumn("url"))?,
+            created: json_str(link, &["timestamp"]).and_then(|t| parse_date(&t)),
+            clicks: json_u64(link, &["clicks"]),
+            tags: vec![],
+        });
+    }
+    not_empty(imported)
+}
+
+// default column order of `yourls_url` table, used when the dump doesn't list columns
+const YOURLS_COLUMNS: [&str; 6] = ["keyword", "url", "title", "timestamp", "ip", "clicks"];
+
+fn parse_yourls_sql(data: &str) -> Result<Vec<ImportedLink>, ImportError> {
+    let mut imported = vec![];
+    // ascii only, so byte positions stay the same as in `data`
+    let lower = data.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find("insert into") {
+        let start = from + start;
+        let values = match lower[start..].find("values") {
+            Some(v) => start + v,
+            None => break,
+        };
+        let statement = &data[start..values];
+        let (rows, end) = sql_tuples(&data[values + "values".len()..]);
+        from = values + "values".len() + end;
+        // only links table, dumps also contain options and log tables
+        let table = statement["insert into".len()..].split('(').next().unwrap_or_default();
+        if !table.trim().trim_matches('`').ends_with("url") {
+            continue;
+        }
+        let columns: Vec<String> = match (statement.find('('), statement.rfind(')')) {
+            (Some(s), Some(e)) if s < e => statement[s + 1..e]
+                .split(',')
+                .map(|c| c.trim().trim_matches('`').to_lowercase())
+                .collect(),
+            _ => YOURLS_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        };
+        let column = |name: &str| columns.iter().position(|c| c == name);
+        let keyword = column("keyword").ok_or(ImportError::MissingColumn("keyword"))?;
+        let url = column("url").ok_or(ImportError::MissingColumn("url"))?;
+        let timestamp = column("timestamp");
+        let clicks = column("clicks");
+        for row in rows {
+            let get = |i: Option<usize>| i.and_then(|i| row.get(i)).and_then(|v| v.clone());
+            imported.push(ImportedLink {
+                name: get(Some(keyword)).unwrap_or_default(),
+                domain: get(Some(url)).unwrap_or_default(),
+                created: get(timestamp).and_then(|t| parse_date(&t)),
+                clicks: get(clicks).and_then(|c| c.parse().ok()),
+                tags: vec![],
+            });
+        }
+    }
+    not_empty(imported)
+}
+
+// reads `(...),(...);` returning values of every tuple (NULL as None) and where the statement ended
+fn sql_tuples(sql: &str) -> (Vec<Vec<Option<String>>>, usize) {
+    let mut tuples = vec![];
+    let mut tuple: Vec<Option<String>> = vec![];
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    let mut depth = 0;
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if quoted {
+            match c {
+                '\\' => {
+                    if let Some((_, e)) = chars.next() {
+                        value.push(match e {
+                            'n' => '\n',
+                            'r' => '\r',
+                            't' => '\t',
+                            '0' => '\0',
+                            e => e,
+                        });
+                    }
+                }
+                '\'' if chars.peek().map(|(_, n)| *n) == Some('\'') => {
+                    chars.next();
+                    value.push('\'');
+                }
+                '\'' => quoted = false,
+                c => value.push(c),
+            }
+            continue;
+        }
+        match c {
+            '\'' => {
+                quoted = true;
+                was_quoted = true;
+            }
+            '(' => {
+                depth += 1;
+                tuple.clear();
+                value.clear();
+                was_quoted = false;
+            }
+            ',' | ')' if depth > 0 => {
+                let v = value.trim();
+                tuple.push(match was_quoted {
+                    true => Some(value.clone()),
+                    false if v.eq_ignore_ascii_case("null") => None,
+                    false => Some(v.to_string()),
+                });
+                value.clear();
+                was_quoted = false;
+                if c == ')' {
+                    depth -= 1;
+                    tuples.push(std::mem::take(&mut tuple));
+                }
+            }
+            ';' if depth == 0 => return (tuples, i + 1),
+            // values outside of quotes e.g. numbers and NULL
+            c if depth > 0 && !was_quoted => value.push(c),
+            _ => {}
+        }
+    }
+    (tuples, sql.len())
+}
+
+///////////
+// BITLY
+///////////
+
+// CSV export from bitly links page
+pub(crate) fn parse_bitly(data: &str) -> Result<Vec<ImportedLink>, ImportError> {
+    parse_csv(data, CsvColumns {
+        name: &["custom link", "custom bitlink", "custom_bitlinks", "bitlink", "short url", "link"],
+        domain: &["long url", "long_url", "original url", "destination url"],
+        created: &["date created", "created", "created at", "created_at"],
+        clicks: &["total clicks", "clicks", "engagements"],
+        tags: &["tags"],
+    })
+}
+
+////////////
+// SHLINK
+////////////
+
+// CSV export from shlink-web-client or JSON from `/rest/v3/short-urls` api
+pub(crate) fn parse_shlink(data: &str) -> Result<Vec<ImportedLink>, ImportError> {
+    let trimmed = data.trim_start();
+    if !trimmed.starts_with('{') && !trimmed.starts_with('[') {
+        return parse_csv(data, CsvColumns {
+            name: &["shortcode", "short code", "shorturl", "short url"],
+            domain: &["longurl", "long url"],
+            created: &["createdat", "datecreated", "created at", "date created"],
+            clicks: &["visits", "visitscount", "visits count"],
+            tags: &["tags"],
+        });
+    }
+    let json: Value = serde_json::from_str(data).map_err(|_| ImportError::NotJson)?;
+    let links = match &json {
+        Value::Array(a) => a,
+        Value::Object(o) => match o.get("shortUrls").and_then(|s| s.get("data")).or_else(|| o.get("data")) {
+            Some(Value::Array(a)) => a,
+            _ => return Err(ImportError::NothingFound),
+        },
+        _ => return Err(ImportError::NothingFound),
+    };
+    let mut imported = vec![];
+    for link in links {
+        let name = match json_str(link, &["shortCode"]) {
+            Some(c) => c,
+            None => last_segment(&json_str(link, &["shortUrl"]).ok_or(ImportError::MissingColumn("shortCode"))?),
+        };
+        imported.push(ImportedLink {
+            name,
+            domain: json_str(link, &["longUrl"]).ok_or(ImportError::MissingColumn("longUrl"))?,
+            created: json_str(link, &["dateCreated"]).and_then(|d| parse_date(&d)),
+            clicks: json_u64(link, &["visitsCount"]).or_else(|| link.get("visitsSummary").and_then(|v| json_u64(v, &["total"]))),
+            tags: link
+                .get("tags")
+                .and_then(|t| t.as_array())
+                .map(|t| t.iter().filter_map(|t| t.as_str().map(|t| t.to_string())).collect())
+                .unwrap_or_default(),
+        });
+    }
+    not_empty(imported)
+}
+
+//////////
+// OTHER
+//////////
+
+// possible names of the columns, matched case insensitive
+struct CsvColumns {
+    name: &'static [&'static str],
+    domain: &'static [&'static str],
+    created: &'static [&'static str],
+    clicks: &'static [&'static str],
+    tags: &'static [&'static str],
+}
+
+fn parse_csv(data: &str, columns: CsvColumns) -> Result<Vec<ImportedLink>, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|_| ImportError::NotCsv)?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    // every matching column in order of names, rows use the first one which isn't empty
+    // e.g. bitly leaves `custom link` empty for links without a custom back-half
+    let columns_of = |names: &[&str]| -> Vec<usize> { names.iter().filter_map(|n| headers.iter().position(|h| h == n)).collect() };
+    let name = columns_of(columns.name);
+    let domain = columns_of(columns.domain);
+    if name.is_empty() {
+        return Err(ImportError::MissingColumn(columns.name[0]));
+    }
+    if domain.is_empty() {
+        return Err(ImportError::MissingColumn(columns.domain[0]));
+    }
+    let created = columns_of(columns.created);
+    let clicks = columns_of(columns.clicks);
+    let tags = columns_of(columns.tags);
+    let mut imported = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|_| ImportError::NotCsv)?;
+        let get = |columns: &[usize]| columns.iter().filter_map(|i| record.get(*i)).find(|v| !v.is_empty());
+        imported.push(ImportedLink {
+            name: get(&name).map(last_segment).unwrap_or_default(),
+            domain: get(&domain).unwrap_or_default().to_string(),
+            created: get(&created).and_then(parse_date),
+            clicks: get(&clicks).and_then(|c| c.parse().ok()),
+            tags: get(&tags)
+                .map(|t| t.split([',', '|']).map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect())
+                .unwrap_or_default(),
+        });
+    }
+    not_empty(imported)
+}
+
+// keyword of short url e.g. `https://bit.ly/abc` -> `abc`, plain keywords are left unchanged
+fn last_segment(short: &str) -> String {
+    let short = short.trim_end_matches('/');
+    match short.find("://") {
+        Some(_) => short.rsplit('/').next().unwrap_or(short).to_string(),
+        None => short.to_string(),
+    }
+}
+
+// accepts RFC 3339, `2022-08-20 10:00:00` (as UTC), `2022-08-20T10:00:00+0000` and `2022-08-20`
+pub(crate) fn parse_date(date: &str) -> Option<DateTime> {
+    let date = date.trim();
+    if let Ok(d) = DateTime::parse_rfc3339_str(date) {
+        return Some(d);
+    }
+    // dates are ascii, offsets below are fixed byte positions
+    if !date.is_ascii() {
+        return None;
+    }
+    let mut date = date.replacen(' ', "T", 1);
+    if date.len() == 10 {
+        date += "T00:00:00";
+    }
+    // `+0000` -> `+00:00`
+    let bytes = date.as_bytes();
+    if date.len() > 5 && matches!(bytes[date.len() - 5], b'+' | b'-') && bytes[date.len() - 3] != b':' {
+        date.insert(date.len() - 2, ':');
+    } else if date.len() == 19 {
+        date += "Z";
+    }
+    DateTime::parse_rfc3339_str(date).ok()
+}
+
+fn json_str(v: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|k| match v.get(k) {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+fn json_u64(v: &Value, keys: &[&str]) -> Option<u64> {
+    keys.iter().find_map(|k| match v.get(k) {
+        Some(Value::Number(n)) => n.as_u64(),
+        Some(Value::String(s)) => s.parse().ok(),
+        _ => None,
+    })
+}
+
+fn not_empty(imported: Vec<ImportedLink>) -> Result<Vec<ImportedLink>, ImportError> {
+    if imported.is_empty() {
+        Err(ImportError::NothingFound)
+    } else {
+        Ok(imported)
+    }
+}
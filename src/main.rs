@@ -1,17 +1,52 @@
+mod access;
 mod api;
+mod chains;
+mod clicks;
+mod config;
 mod database;
+mod history;
+mod host_rules;
+mod import;
+mod pages;
+mod patterns;
+mod qr;
+mod random_names;
+mod redirect;
+mod schedule;
+mod targeting;
 #[cfg(test)]
 mod tests;
+mod validation;
 
 #[macro_use]
 extern crate rocket;
 
-use mongodb::bson::doc;
-use rocket::response::Redirect;
+use mongodb::bson::{self, doc, Bson, oid::ObjectId};
+use mongodb::options::AggregateOptions;
+use rocket::form::Form;
+use rocket::futures::TryStreamExt;
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::http::uri::{Origin, Segments, fmt::Path};
+use rocket::{Build, Data, Either, Request, Rocket, Route};
+use rocket::route::{self, Handler};
+use rocket::response::{Redirect, content::RawHtml};
+use rocket::time::Duration;
+use serde::Deserialize;
+use crate::access::Access;
 use crate::api::v1::mount_v1;
+use crate::chains::{ChainConfig, DbLinks, LinkName, resolve_chain};
+use crate::clicks::record_click;
 use crate::database::{connect, Domain};
+use crate::host_rules::host_rules;
+use crate::pages::{LandingConfig, PreviewConfig, Served, landing_page, miss, not_found, password_page, preview_page, server_error};
+use crate::patterns::{PatternLinks, pattern_rules};
+use crate::redirect::{HostsConfig, RequestHost, forward_url, namespace_bson, tag_url};
+use crate::targeting::{VariantConfig, Visitor, choose};
+use crate::validation::{NameConfig, UrlConfig, validate_url};
 
 const DOMAIN: &str = "https://lmpk.tk";
+// change to change redirecting prefix e.g. example.com/r/<name of redirect>
+const REDIRECT_PREFIX: &str = "/r";
 const DATABASE_NAME: &str = "redirector";
 // collection for domains in debug
 #[cfg(debug_assertions)]
@@ -25,31 +60,291 @@ const AUTH_COLLECTION: &str = "devAuth";
 // collection for auth codes in release
 #[cfg(not(debug_assertions))]
 const AUTH_COLLECTION: &str = "auth";
+// collection for host block/allow rules in debug
+#[cfg(debug_assertions)]
+const HOST_RULES_COLLECTION: &str = "devHostRules";
+// collection for host block/allow rules in release
+#[cfg(not(debug_assertions))]
+const HOST_RULES_COLLECTION: &str = "hostRules";
+// collection for counters of sequential names in debug
+#[cfg(debug_assertions)]
+const COUNTERS_COLLECTION: &str = "devCounters";
+// collection for counters of sequential names in release
+#[cfg(not(debug_assertions))]
+const COUNTERS_COLLECTION: &str = "counters";
+// collection for clicks of redirects in debug
+#[cfg(debug_assertions)]
+const CLICKS_COLLECTION: &str = "devClicks";
+// collection for clicks of redirects in release
+#[cfg(not(debug_assertions))]
+const CLICKS_COLLECTION: &str = "clicks";
+// collection for previous versions of redirects in debug
+#[cfg(debug_assertions)]
+const HISTORY_COLLECTION: &str = "devHistory";
+// collection for previous versions of redirects in release
+#[cfg(not(debug_assertions))]
+const HISTORY_COLLECTION: &str = "history";
+// collection for wildcard and regex redirects in debug
+#[cfg(debug_assertions)]
+const PATTERNS_COLLECTION: &str = "devPatterns";
+// collection for wildcard and regex redirects in release
+#[cfg(not(debug_assertions))]
+const PATTERNS_COLLECTION: &str = "patterns";
 
-#[get("/<name>")]
-async fn redirector(name: String) -> Redirect {
+#[get("/<name>/<rest..>")]
+async fn redirector(name: String, rest: Segments<'_, Path>, uri: &Origin<'_>, host: RequestHost, visitor: Visitor, cookies: &CookieJar<'_>) -> Served {
+    let hosts = config::load::<HostsConfig>();
+    let namespace = hosts.namespace(host.0.as_deref());
+    let fallback = hosts.fallback(namespace.as_deref());
+    let dom = ok_return!(find_redirect(&name, &namespace).await, Served::Redirect(Box::new(Redirect::to(fallback))));
+    // `/r/<name>+` shows where the redirect goes
+    if let (None, Some(base), true) = (&dom, name.strip_suffix('+'), rest.is_empty()) {
+        return preview(base, &namespace, fallback, &visitor).await;
+    }
+    if let Some(d) = dom.as_ref().filter(|d| d.access_password.is_some()) {
+        return Served::Page((Status::Ok, password_page(&d.name, "")));
+    }
+    serve(dom, &name, &namespace, &rest.collect::<Vec<_>>(), uri.query().map(|q| q.as_str()), &visitor, cookies).await
+}
+
+// redirects to destination of the found redirect, through its interstitial when it has one
+async fn serve(dom: Option<Domain>, name: &str, namespace: &Option<String>, rest: &[&str], query: Option<&str>, visitor: &Visitor, cookies: &CookieJar<'_>) -> Served {
+    let shown = dom.clone().filter(|d| d.interstitial);
+    let (dom, click) = pick(dom, visitor, cookies);
+    match (destination(dom, name, namespace, rest, query).await, shown) {
+        (Some(d), shown) => {
+            if let Some((id, variant)) = click {
+                record_click(id, variant);
+            }
+            match shown {
+                Some(shown) => {
+                    let secs = config::load::<PreviewConfig>().interstitial_seconds;
+                    Served::Page((Status::Ok, preview_page(&shown.name, Some(&shown), Some(&d), Some(secs))))
+                }
+                None => Served::Redirect(Box::new(Redirect::to(d))),
+            }
+        }
+        (None, _) => miss(name, namespace, config::load::<HostsConfig>().fallback(namespace.as_deref())).await,
+    }
+}
+
+#[get("/preview/<name>")]
+async fn preview_redirect(name: String, host: RequestHost, visitor: Visitor) -> Served {
+    let hosts = config::load::<HostsConfig>();
+    let namespace = hosts.namespace(host.0.as_deref());
+    let fallback = hosts.fallback(namespace.as_deref());
+    preview(&name, &namespace, fallback, &visitor).await
+}
+
+async fn preview(name: &str, namespace: &Option<String>, fallback: String, visitor: &Visitor) -> Served {
+    let dom = ok_return!(find_redirect(name, namespace).await, Served::Redirect(Box::new(Redirect::to(fallback))));
+    if let Some(d) = dom.as_ref().filter(|d| d.access_password.is_some()) {
+        return Served::Page((Status::Ok, preview_page(&d.name, Some(d), None, None)));
+    }
+    // preview shows the default instead of a random variant
+    let dom = dom.map(|mut d| {
+        d.variants.clear();
+        choose(&mut d, visitor, None);
+        d
+    });
+    let shown = dom.clone();
+    match destination(dom, name, namespace, &[], None).await {
+        Some(d) => Served::Page((Status::Ok, preview_page(name, shown.as_ref(), Some(&d), None))),
+        None => miss(name, namespace, fallback).await,
+    }
+}
+
+#[derive(FromForm)]
+struct Unlock {
+    password: String,
+}
+
+// password form of protected redirects posts here
+#[post("/<name>/<rest..>", data = "<form>")]
+async fn unlock(name: String, rest: Segments<'_, Path>, uri: &Origin<'_>, host: RequestHost, visitor: Visitor, cookies: &CookieJar<'_>, form: Form<Unlock>) -> Served {
+    let hosts = config::load::<HostsConfig>();
+    let namespace = hosts.namespace(host.0.as_deref());
+    let fallback = hosts.fallback(namespace.as_deref());
+    let dom = ok_return!(find_redirect(&name, &namespace).await, Served::Redirect(Box::new(Redirect::to(fallback))));
+    if let Some(d) = &dom {
+        if let Some(hash) = &d.access_password {
+            // failures are counted per redirect and per redirect and address
+            let client = visitor.ip.map(|i| i.to_string()).unwrap_or_default();
+            match access::verify(&d._id.to_hex(), &client, &form.password, hash) {
+                Access::Granted => {}
+                Access::Denied => return Served::Page((Status::Unauthorized, password_page(&d.name, "Wrong password."))),
+                Access::Locked(secs) => {
+                    let message = format!("Too many wrong passwords, try again in {} seconds.", secs);
+                    return Served::Page((Status::TooManyRequests, password_page(&d.name, &message)));
+                }
+            }
+        }
+    }
+    serve(dom, &name, &namespace, &rest.collect::<Vec<_>>(), uri.query().map(|q| q.as_str()), &visitor, cookies).await
+}
+
+// applies targets and variants, returns what the click is recorded as
+// sticky variants are kept in a cookie per redirect
+fn pick(dom: Option<Domain>, visitor: &Visitor, cookies: &CookieJar<'_>) -> (Option<Domain>, Option<(ObjectId, Option<String>)>) {
+    let mut d = match dom {
+        Some(d) => d,
+        None => return (None, None),
+    };
+    let cookie = format!("variant-{}", d._id);
+    let sticky = cookies.get(&cookie).filter(|_| d.sticky).map(|c| c.value().to_string());
+    let variant = choose(&mut d, visitor, sticky.as_deref());
+    if let (true, Some(v)) = (d.sticky, &variant) {
+        let days = config::load::<VariantConfig>().variant_cookie_days;
+        cookies.add(Cookie::build((cookie, v.clone())).path("/").max_age(Duration::days(days)).same_site(SameSite::Lax));
+    }
+    let click = Some((d._id, variant));
+    (Some(d), click)
+}
+
+// redirect of the host wins over global one with the same name
+// old names kept as aliases find the redirect too
+// inactive and trashed redirects aren't found, scheduled destinations replace the default one
+async fn find_redirect(name: &str, namespace: &Option<String>) -> mongodb::error::Result<Option<Domain>> {
     let col = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
-    let filter = doc! { "name" : name };
-    let dom = ok_return!(col.find_one(filter, None).await, Redirect::to(DOMAIN));
+    let filter = doc! {
+        "$or": [{ "name": name }, { "aliases": name }],
+        "namespace": { "$in": [namespace_bson(namespace), Bson::Null] },
+        "deleted": null,
+    };
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$sort": { "namespace": -1 } },
+        doc! { "$limit": 1 },
+        // default params of the owner come with the redirect instead of another query
+        doc! { "$lookup": { "from": AUTH_COLLECTION, "localField": "owner", "foreignField": "_id", "as": "owner_auth" } },
+        doc! { "$addFields": { "owner_params": { "$arrayElemAt": ["$owner_auth.params", 0] } } },
+        doc! { "$project": { "owner_auth": 0 } },
+    ];
+    let options = AggregateOptions::builder().collation(config::load::<NameConfig>().collation()).build();
+    let dom = match col.aggregate(pipeline, options).await?.try_next().await? {
+        Some(d) => Some(bson::from_document::<Domain>(d)?),
+        None => None,
+    };
+    Ok(dom.and_then(|d| schedule::resolve(d, schedule::now())))
+}
+
+// destination of the redirect or of a pattern, none on a miss
+async fn destination(dom: Option<Domain>, name: &str, namespace: &Option<String>, rest: &[&str], query: Option<&str>) -> Option<String> {
     match dom {
-        Some(d) => Redirect::to(d.domain),
-        None => Redirect::to(DOMAIN)
+        // host could be blocked after the redirect was created
+        Some(d) if host_rules().await.check(&d.domain).is_err() => None,
+        Some(d) if d.forward => Some(tagged(forward_url(&d.domain, rest, query), &d).await),
+        // only forwarding redirects match longer paths
+        Some(_) if !rest.is_empty() => None,
+        Some(d) => Some(tagged(d.domain.clone(), &d).await),
+        // exact names win over patterns
+        None if rest.is_empty() => {
+            let rules = pattern_rules().await;
+            let destination = rules.resolve(name, namespace).and_then(|d| validate_url(&d, &config::load::<UrlConfig>()).ok())?;
+            // patterns can point to names matched by patterns again, checked on every use
+            let col = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+            let links = PatternLinks { links: DbLinks { col: &col, renamed: None }, rules: &rules };
+            let hosts = config::load::<HostsConfig>();
+            // broken rules are logged when they are loaded
+            if resolve_chain(&LinkName::new(namespace, name), &destination, &config::load::<ChainConfig>(), &hosts, &links).await.is_err() {
+                return None;
+            }
+            host_rules().await.check(&destination).ok().map(|_| destination)
+        }
+        None => None
+    }
+}
+
+// params of the redirect, then default ones of its owner
+async fn tagged(url: String, dom: &Domain) -> String {
+    let url = match &dom.params {
+        Some(p) => tag_url(&url, p),
+        None => url,
+    };
+    match &dom.owner_params {
+        Some(p) => tag_url(&url, p),
+        None => url,
     }
 }
 
 #[get("/")]
-fn index() -> &'static str {
-    "Hello, world!"
+async fn index() -> Either<Redirect, RawHtml<String>> {
+    let conf = config::load::<LandingConfig>();
+    match &conf.landing_redirect {
+        Some(url) => Either::Left(Redirect::to(url.clone())),
+        None => Either::Right(landing_page(&conf).await),
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct RootConfig {
+    // serve redirects also at `/<name>`
+    root_links: bool,
+    // lower rank wins, routes of the api use ranks from -12 to -1
+    root_links_rank: isize,
+}
+
+impl Default for RootConfig {
+    fn default() -> Self {
+        Self {
+            root_links: false,
+            root_links_rank: 20,
+        }
+    }
+}
+
+// root links leave paths starting with reserved names, like `/api/...`, to their own routes and catchers
+#[derive(Clone)]
+struct RootLinks(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for RootLinks {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> route::Outcome<'r> {
+        let first = req.uri().path().segments().next().unwrap_or_default();
+        if config::load::<NameConfig>().reserved_names.iter().any(|r| r.eq_ignore_ascii_case(first)) {
+            return route::Outcome::forward(data, Status::NotFound);
+        }
+        self.0.handle(req, data).await
+    }
+}
+
+// landing page, error pages and redirects under `REDIRECT_PREFIX` and optionally at root
+fn mount_redirects(rocket: Rocket<Build>) -> Rocket<Build> {
+    let conf = config::load::<RootConfig>();
+    let rocket = rocket
+        .mount("/", routes![index, preview_redirect])
+        .mount(REDIRECT_PREFIX, routes![redirector, unlock])
+        .register("/", catchers![not_found, server_error]);
+    if !conf.root_links {
+        return rocket;
+    }
+    rocket.mount("/", root_routes(conf.root_links_rank))
+}
+
+// redirects at `/<name>`, ranked after other routes
+fn root_routes(rank: isize) -> Vec<Route> {
+    routes![redirector, unlock]
+        .into_iter()
+        .map(|mut r| {
+            r.rank = rank;
+            r.handler = Box::new(RootLinks(r.handler));
+            r
+        })
+        .collect()
 }
 
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
+    // every name would be rejected, don't start with a broken charset
+    if let Err(e) = config::load::<NameConfig>().charset() {
+        println!("Name charset in config is not valid: {:?}", e);
+        std::process::exit(1);
+    }
     database::manage_database().await;
+    rocket::tokio::spawn(database::purge_trash());
     // build, mount and launch
-    let rocket = rocket::build()
-        .mount("/", routes![index])
-        // change `r` to change redirecting prefix e.g. example.com/r/<name of redirect>
-        .mount("/r", routes![redirector]);
+    let rocket = mount_redirects(rocket::build());
     let rocket = mount_v1(rocket);
     let _rocket = rocket.launch()
         .await?;
@@ -81,7 +376,7 @@ macro_rules! ok_return {
 macro_rules! add_and {
     ( $s:expr ) => {
         if !$s.is_empty() {
-           $s = $s + " and "
+           $s += " and "
         }
     }
 }
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use mongodb::bson::{doc, Bson, Document, Regex};
+use mongodb::options::FindOptions;
+use rocket::futures::TryStreamExt;
+use rocket::http::Status;
+use rocket::response::{Redirect, content::RawHtml};
+use rocket::Request;
+use serde::Deserialize;
+use crate::{DOMAINS_COLLECTION, Domain, REDIRECT_PREFIX, config, connect};
+use crate::redirect::namespace_bson;
+
+const LANDING: &str = include_str!("pages/landing.html");
+const ERROR: &str = include_str!("pages/error.html");
+const PASSWORD: &str = include_str!("pages/password.html");
+const PREVIEW: &str = include_str!("pages/preview.html");
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub(crate) struct LandingConfig {
+    // `/` redirects here when set
+    pub(crate) landing_redirect: Option<String>,
+    // html file served at `/`, bundled page is used when not set
+    pub(crate) landing_page: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MissBehaviour {
+    // redirect to `fallback_url`
+    Fallback,
+    // html page with similar names
+    Page,
+    // plain text 404
+    NotFound,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct MissConfig {
+    pub(crate) miss_behaviour: MissBehaviour,
+    // html template with `{{title}}`, `{{message}}` and `{{suggestions}}`, bundled page is used when not set
+    pub(crate) error_template: Option<String>,
+    // how many similar names are shown
+    pub(crate) miss_suggestions: usize,
+    // how many names are compared when looking for similar ones
+    pub(crate) miss_scan_limit: i64,
+    // seconds names loaded for suggestions are reused
+    pub(crate) miss_cache_secs: u64,
+    // names loaded from the database for suggestions per minute, misses above it get none
+    pub(crate) miss_lookups_per_minute: usize,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct PreviewConfig {
+    // seconds before redirects with `interstitial` continue to destination
+    pub(crate) interstitial_seconds: u64,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            interstitial_seconds: 5,
+        }
+    }
+}
+
+impl Default for MissConfig {
+    fn default() -> Self {
+        Self {
+            miss_behaviour: MissBehaviour::Fallback,
+            error_template: None,
+            miss_suggestions: 5,
+            miss_scan_limit: 10000,
+            miss_cache_secs: 60,
+            miss_lookups_per_minute: 60,
+        }
+    }
+}
+
+pub(crate) async fn landing_page(conf: &LandingConfig) -> RawHtml<String> {
+    if let Some(path) = &conf.landing_page {
+        match rocket::tokio::fs::read_to_string(path).await {
+            Ok(page) => return RawHtml(page),
+            Err(e) => println!("Could not read landing page '{}': {:?}", path, e),
+        }
+    }
+    RawHtml(LANDING.to_string())
+}
+
+pub(crate) async fn error_page(title: &str, message: &str, suggestions: &[String]) -> RawHtml<String> {
+    let conf = config::load::<MissConfig>();
+    let template = match &conf.error_template {
+        Some(path) => rocket::tokio::fs::read_to_string(path).await.unwrap_or_else(|e| {
+            println!("Could not read error template '{}': {:?}", path, e);
+            ERROR.to_string()
+        }),
+        None => ERROR.to_string(),
+    };
+    let suggestions = if suggestions.is_empty() {
+        "".to_string()
+    } else {
+        let items: Vec<String> = suggestions
+            .iter()
+            .map(|s| format!("<li><a href=\"{}/{}\">{}</a></li>", REDIRECT_PREFIX, escape_html(s), escape_html(s)))
+            .collect();
+        format!("<p>Did you mean:</p><ul>{}</ul>", items.join(""))
+    };
+    RawHtml(render(&template, &[
+        ("title", escape_html(title)),
+        ("message", escape_html(message)),
+        ("suggestions", suggestions),
+    ]))
+}
+
+// form asking for the access password of a redirect, posts back to the same url
+pub(crate) fn password_page(name: &str, message: &str) -> RawHtml<String> {
+    RawHtml(render(PASSWORD, &[("name", escape_html(name)), ("message", escape_html(message))]))
+}
+
+// where a redirect goes, with countdown when shown before redirecting
+// destination is none for redirects with access password
+pub(crate) fn preview_page(name: &str, dom: Option<&Domain>, destination: Option<&str>, countdown: Option<u64>) -> RawHtml<String> {
+    let title = dom.and_then(|d| d.title.clone()).unwrap_or_else(|| name.to_string());
+    let description = dom.and_then(|d| d.description.clone()).unwrap_or_default();
+    let created = dom
+        .and_then(|d| d.created)
+        .and_then(|c| c.try_to_rfc3339_string().ok())
+        .map(|c| c.chars().take(10).collect())
+        .unwrap_or_else(|| "by a pattern".to_string());
+    let link = match destination {
+        Some(d) => format!("<a href=\"{}\" rel=\"noreferrer\">{}</a>", escape_html(d), escape_html(d)),
+        None => "hidden until the password is entered".to_string(),
+    };
+    let (refresh, countdown) = match (destination, countdown) {
+        (Some(d), Some(secs)) => (
+            format!("<meta http-equiv=\"refresh\" content=\"{};url={}\">", secs, escape_html(d)),
+            format!(
+                "<p>Continuing in <span id=\"countdown\">{}</span> seconds.</p>\
+                <script>let s = {}; setInterval(() => {{ if (s > 0) document.getElementById(\"countdown\").textContent = --s; }}, 1000);</script>",
+                secs, secs
+            ),
+        ),
+        _ => ("".to_string(), "".to_string()),
+    };
+    RawHtml(render(PREVIEW, &[
+        ("refresh", refresh),
+        ("title", escape_html(&title)),
+        ("description", escape_html(&description)),
+        ("name", escape_html(name)),
+        ("destination", link),
+        ("created", escape_html(&created)),
+        ("countdown", countdown),
+    ]))
+}
+
+// replaces `{{key}}` with values, values have to be escaped already
+pub(crate) fn render(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |page, (key, value)| page.replace(&format!("{{{{{}}}}}", key), value))
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// more cached name lists are dropped all at once
+const MAX_CACHED: usize = 1000;
+
+// names by namespace and first letter, with the time they were loaded
+type NameCache = HashMap<(Option<String>, char), (Instant, Arc<Vec<String>>)>;
+
+static NAMES: LazyLock<Mutex<NameCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+// start of the current minute and lookups in it
+static LOOKUPS: Mutex<Option<(Instant, usize)>> = Mutex::new(None);
+
+// names of redirects similar to the missing one
+// only names of the same short host starting with the same letter are compared, protected ones aren't shown
+pub(crate) async fn similar_names(name: &str, namespace: &Option<String>) -> Vec<String> {
+    let conf = config::load::<MissConfig>();
+    let first = match name.chars().next() {
+        Some(c) => c,
+        None => return vec![],
+    };
+    let key = (namespace.clone(), first.to_lowercase().next().unwrap_or(first));
+    let cached = NAMES
+        .lock()
+        .ok()
+        .and_then(|c| c.get(&key).filter(|(t, _)| t.elapsed() < Duration::from_secs(conf.miss_cache_secs)).map(|(_, n)| n.clone()));
+    let names = match cached {
+        Some(n) => n,
+        None => {
+            if !lookup_allowed(conf.miss_lookups_per_minute) {
+                return vec![];
+            }
+            let names = Arc::new(load_names(namespace, first, conf.miss_scan_limit).await);
+            if let Ok(mut cache) = NAMES.lock() {
+                // expired entries go first, then the oldest one
+                if cache.len() >= MAX_CACHED && !cache.contains_key(&key) {
+                    cache.retain(|_, (t, _)| t.elapsed() < Duration::from_secs(conf.miss_cache_secs));
+                }
+                if cache.len() >= MAX_CACHED && !cache.contains_key(&key) {
+                    if let Some(oldest) = cache.iter().min_by_key(|(_, (t, _))| *t).map(|(k, _)| k.clone()) {
+                        cache.remove(&oldest);
+                    }
+                }
+                cache.insert(key, (Instant::now(), names.clone()));
+            }
+            names
+        }
+    };
+    suggestions(name, names.iter().map(String::as_str), conf.miss_suggestions)
+}
+
+fn lookup_allowed(per_minute: usize) -> bool {
+    let mut lookups = match LOOKUPS.lock() {
+        Ok(l) => l,
+        Err(_) => return false,
+    };
+    let count = match *lookups {
+        Some((start, count)) if start.elapsed() < Duration::from_secs(60) => count,
+        _ => {
+            *lookups = Some((Instant::now(), 0));
+            0
+        }
+    };
+    if count >= per_minute {
+        return false;
+    }
+    if let Some((_, c)) = lookups.as_mut() {
+        *c += 1;
+    }
+    true
+}
+
+// prefix queries in both cases use the index on names
+async fn load_names(namespace: &Option<String>, first: char, limit: i64) -> Vec<String> {
+    let col = connect().await.collection::<Document>(DOMAINS_COLLECTION);
+    let options = FindOptions::builder()
+        .projection(doc! { "name": 1 })
+        .limit(limit)
+        .build();
+    let prefixes: Vec<Bson> = first
+        .to_lowercase()
+        .chain(first.to_uppercase())
+        .map(|c| Bson::RegularExpression(Regex { pattern: format!("^{}", regex::escape(&c.to_string())), options: "".to_string() }))
+        .collect();
+    let filter = doc! {
+        "name": { "$in": prefixes },
+        "namespace": namespace_bson(namespace),
+        "access_password": null,
+        "deleted": null,
+    };
+    match col.find(filter, options).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Document>>().await {
+            Ok(found) => found.iter().filter_map(|d| d.get_str("name").ok().map(str::to_string)).collect(),
+            Err(e) => {
+                println!("{:?}", e);
+                vec![]
+            }
+        },
+        Err(e) => {
+            println!("{:?}", e);
+            vec![]
+        }
+    }
+}
+
+// closest names by edit distance, at most a third of the name can differ
+pub(crate) fn suggestions<'a>(name: &str, candidates: impl Iterator<Item = &'a str>, limit: usize) -> Vec<String> {
+    let name = name.to_lowercase();
+    let max = (name.chars().count() / 3).max(1);
+    let mut found: Vec<(usize, &str)> = candidates
+        .filter_map(|c| {
+            let distance = levenshtein(&name, &c.to_lowercase());
+            (distance <= max).then_some((distance, c))
+        })
+        .collect();
+    found.sort();
+    found.into_iter().take(limit).map(|(_, c)| c.to_string()).collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[catch(404)]
+pub(crate) async fn not_found(req: &Request<'_>) -> RawHtml<String> {
+    error_page("Not found", &format!("Nothing is at '{}'.", req.uri().path()), &[]).await
+}
+
+#[catch(500)]
+pub(crate) async fn server_error() -> RawHtml<String> {
+    error_page("Server error", "Something went wrong, try again later.", &[]).await
+}
+
+// response of `redirector`
+#[derive(Responder)]
+pub(crate) enum Served {
+    Redirect(Box<Redirect>),
+    Page((Status, RawHtml<String>)),
+    Plain((Status, &'static str)),
+}
+
+// response for a name without redirect, depends on `miss_behaviour`
+pub(crate) async fn miss(name: &str, namespace: &Option<String>, fallback: String) -> Served {
+    match config::load::<MissConfig>().miss_behaviour {
+        MissBehaviour::Fallback => Served::Redirect(Box::new(Redirect::to(fallback))),
+        MissBehaviour::Page => {
+            let similar = similar_names(name, namespace).await;
+            let page = error_page("Not found", &format!("There is no redirect named '{}'.", name), &similar).await;
+            Served::Page((Status::NotFound, page))
+        }
+        MissBehaviour::NotFound => Served::Plain((Status::NotFound, "Not found")),
+    }
+}
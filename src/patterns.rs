@@ -0,0 +1,245 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+use mongodb::bson::{doc, DateTime, oid::ObjectId};
+use mongodb::options::FindOptions;
+use regex::{Regex, RegexBuilder};
+use rocket::futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::{DOMAINS_COLLECTION, Domain, PATTERNS_COLLECTION, config, connect};
+use crate::api::openapi::{DateTimeSchema, ObjectIdSchema};
+use crate::chains::{ChainConfig, DbLinks, LinkName, Links, resolve_chain};
+use crate::redirect::HostsConfig;
+
+// longer patterns are rejected, compiled rules are limited in size too
+const MAX_PATTERN_LENGTH: usize = 256;
+const MAX_REGEX_SIZE: usize = 1 << 16;
+// `$1`, `$name`, `${1}` or `${name}` in destination
+const PLACEHOLDER: &str = r"\$(?:\{(\w+)\}|(\w+))";
+
+#[derive(Deserialize, Serialize, JsonSchema, FromFormField, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PatternKind {
+    // `*` matches any text, `?` one character, captured as `{1}`, `{2}`...
+    Wildcard,
+    // captures as `$1` or `${name}`
+    Regex,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub(crate) struct PatternRule {
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) _id: ObjectId,
+    pub(crate) kind: PatternKind,
+    pub(crate) pattern: String,
+    // destination with placeholders for captured parts of the name
+    pub(crate) destination: String,
+    // rules with higher priority are tried first
+    #[serde(default)]
+    pub(crate) priority: i32,
+    // short host namespace, none for every host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) namespace: Option<String>,
+    #[schemars(with = "ObjectIdSchema")]
+    pub(crate) owner: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<DateTimeSchema>")]
+    pub(crate) created: Option<DateTime>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum PatternError {
+    TooLong,
+    NotValid(String),
+    MissingGroup(String),
+    // captures in scheme or host would let one rule redirect anywhere
+    HostCapture,
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::TooLong => write!(f, "pattern is longer than {} characters", MAX_PATTERN_LENGTH),
+            PatternError::NotValid(e) => write!(f, "pattern is not valid ({})", e),
+            PatternError::MissingGroup(g) => write!(f, "destination uses '{}' which pattern doesn't capture", g),
+            PatternError::HostCapture => write!(f, "destination can use captured parts only in its path and query"),
+        }
+    }
+}
+
+pub(crate) struct PatternRules {
+    rules: Vec<(PatternRule, Regex)>,
+}
+
+// rules are cached until they change through the api
+static CACHE: RwLock<Option<Arc<PatternRules>>> = RwLock::new(None);
+
+// all rules from database in order they are tried
+pub(crate) async fn pattern_rules() -> Arc<PatternRules> {
+    if let Some(rules) = CACHE.read().ok().and_then(|c| c.clone()) {
+        return rules;
+    }
+    let col = connect().await.collection::<PatternRule>(PATTERNS_COLLECTION);
+    let options = FindOptions::builder().sort(doc! { "priority": -1, "_id": 1 }).build();
+    let found = match col.find(None, options).await {
+        Ok(cursor) => cursor.try_collect::<Vec<PatternRule>>().await,
+        Err(e) => Err(e),
+    };
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => {
+            // not cached, next lookup tries the database again
+            println!("Could not read pattern rules: {:?}", e);
+            return Arc::new(PatternRules::new(vec![]));
+        }
+    };
+    let rules = Arc::new(PatternRules::new(found));
+    rules.report_chains().await;
+    if let Ok(mut cache) = CACHE.write() {
+        *cache = Some(rules.clone());
+    }
+    rules
+}
+
+pub(crate) fn clear_cache() {
+    if let Ok(mut cache) = CACHE.write() {
+        *cache = None;
+    }
+}
+
+impl PatternRule {
+    // compiles rule and checks destination uses only captured groups
+    pub(crate) fn compile(&self) -> Result<Regex, PatternError> {
+        if self.pattern.chars().count() > MAX_PATTERN_LENGTH {
+            return Err(PatternError::TooLong);
+        }
+        let source = match self.kind {
+            PatternKind::Wildcard => wildcard_regex(&self.pattern),
+            PatternKind::Regex => self.pattern.clone(),
+        };
+        // regex crate runs in linear time, size limit keeps huge repetitions out
+        let regex = RegexBuilder::new(&source)
+            .size_limit(MAX_REGEX_SIZE)
+            .dfa_size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map_err(|e| PatternError::NotValid(e.to_string()))?;
+        let template = template(&self.destination);
+        let placeholder = Regex::new(PLACEHOLDER).map_err(|e| PatternError::NotValid(e.to_string()))?;
+        let host_end = authority_end(&template);
+        for caps in placeholder.captures_iter(&template) {
+            if caps.get(0).is_some_and(|c| c.start() < host_end) {
+                return Err(PatternError::HostCapture);
+            }
+            let name = caps.get(1).or(caps.get(2)).map(|n| n.as_str()).unwrap_or_default();
+            let known = match name.parse::<usize>() {
+                Ok(n) => n < regex.captures_len(),
+                Err(_) => regex.capture_names().flatten().any(|c| c == name),
+            };
+            if !known {
+                return Err(PatternError::MissingGroup(caps[0].to_string()));
+            }
+        }
+        Ok(regex)
+    }
+
+    // destination with every placeholder filled, for checking it is a valid url
+    pub(crate) fn sample_destination(&self) -> String {
+        match Regex::new(PLACEHOLDER) {
+            Ok(r) => r.replace_all(&template(&self.destination), "x").to_string(),
+            Err(_) => self.destination.clone(),
+        }
+    }
+}
+
+// `gh-*` -> `^gh-(.*)$`
+fn wildcard_regex(pattern: &str) -> String {
+    let mut source = "^".to_string();
+    for c in pattern.chars() {
+        match c {
+            '*' => source += "(.*)",
+            '?' => source += "(.)",
+            c => source += &regex::escape(&c.to_string()),
+        }
+    }
+    source + "$"
+}
+
+// position where path of destination starts, everything before it is scheme and host
+fn authority_end(destination: &str) -> usize {
+    let start = destination.find("://").map(|i| i + 3).unwrap_or(0);
+    destination[start..].find(['/', '?', '#']).map(|i| start + i).unwrap_or(destination.len())
+}
+
+// `{1}` placeholders of wildcards work the same as `${1}`
+fn template(destination: &str) -> String {
+    match Regex::new(r"\{(\d+)\}") {
+        Ok(r) => r.replace_all(destination, "$${$1}").to_string(),
+        Err(_) => destination.to_string(),
+    }
+}
+
+impl PatternRules {
+    pub(crate) fn new(rules: Vec<PatternRule>) -> Self {
+        let rules = rules
+            .into_iter()
+            .filter_map(|r| match r.compile() {
+                Ok(regex) => Some((r, regex)),
+                Err(e) => {
+                    println!("Skipping pattern rule '{}': {}", r.pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    // logs rules whose destinations lead into loops or too long chains, once per load instead of on every use
+    async fn report_chains(&self) {
+        let col = connect().await.collection::<Domain>(DOMAINS_COLLECTION);
+        let links = PatternLinks { links: DbLinks { col: &col, renamed: None }, rules: self };
+        let conf = config::load::<ChainConfig>();
+        let hosts = config::load::<HostsConfig>();
+        for (rule, _) in &self.rules {
+            let link = LinkName::new(&rule.namespace, &rule.pattern);
+            if let Err(e) = resolve_chain(&link, &rule.sample_destination(), &conf, &hosts, &links).await {
+                println!("Pattern rule '{}' is not followed: {}", rule.pattern, e);
+            }
+        }
+    }
+
+    pub(crate) fn list(&self) -> Vec<&PatternRule> {
+        self.rules.iter().map(|(r, _)| r).collect()
+    }
+
+    // destination of the first rule matching name, rules of the host's namespace before global ones
+    pub(crate) fn resolve(&self, name: &str, namespace: &Option<String>) -> Option<String> {
+        let scoped = self.rules.iter().filter(|(r, _)| namespace.is_some() && r.namespace == *namespace);
+        let global = self.rules.iter().filter(|(r, _)| r.namespace.is_none());
+        scoped.chain(global).find_map(|(rule, regex)| {
+            let caps = regex.captures(name)?;
+            let mut destination = String::new();
+            caps.expand(&template(&rule.destination), &mut destination);
+            Some(destination)
+        })
+    }
+}
+
+// redirects and patterns, destinations of patterns can point to other names and patterns too
+pub(crate) struct PatternLinks<'a> {
+    pub(crate) links: DbLinks<'a>,
+    pub(crate) rules: &'a PatternRules,
+}
+
+impl Links for PatternLinks<'_> {
+    async fn destination(&self, link: &LinkName) -> Option<String> {
+        match self.links.destination(link).await {
+            Some(d) => Some(d),
+            None => self.rules.resolve(&link.name, &link.namespace),
+        }
+    }
+
+    async fn referrers(&self, link: &LinkName, base: &Url, root_links: bool, hosts: &HostsConfig) -> Vec<LinkName> {
+        self.links.referrers(link, base, root_links, hosts).await
+    }
+}
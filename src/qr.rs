@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use qrcode::{Color, EcLevel, QrCode};
+use schemars::JsonSchema;
+
+// requested sizes are clamped to this many pixels
+pub(crate) const MAX_QR_SIZE: u32 = 4096;
+const MAX_QR_MARGIN: u32 = 64;
+
+#[derive(FromFormField, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum QrFormat {
+    Png,
+    Svg,
+}
+
+// how much of the code can be damaged and still read
+#[derive(FromFormField, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum QrLevel {
+    // 7%
+    L,
+    // 15%
+    M,
+    // 25%
+    Q,
+    // 30%
+    H,
+}
+
+impl From<QrLevel> for EcLevel {
+    fn from(level: QrLevel) -> Self {
+        match level {
+            QrLevel::L => EcLevel::L,
+            QrLevel::M => EcLevel::M,
+            QrLevel::Q => EcLevel::Q,
+            QrLevel::H => EcLevel::H,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum QrError {
+    Color(String),
+    TooLong,
+    Encode(String),
+}
+
+impl Display for QrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QrError::Color(c) => write!(f, "'{}' is not a color, use `rgb`, `rrggbb` or `rrggbbaa`", c),
+            QrError::TooLong => write!(f, "url is too long for a qr code"),
+            QrError::Encode(e) => write!(f, "could not encode image ({})", e),
+        }
+    }
+}
+
+pub(crate) struct QrOptions {
+    // width and height in pixels, png is rounded down to whole modules
+    pub(crate) size: u32,
+    // quiet zone around the code in modules
+    pub(crate) margin: u32,
+    pub(crate) level: QrLevel,
+    pub(crate) foreground: [u8; 4],
+    pub(crate) background: [u8; 4],
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        Self {
+            size: 256,
+            margin: 4,
+            level: QrLevel::M,
+            foreground: [0, 0, 0, 255],
+            background: [255, 255, 255, 255],
+        }
+    }
+}
+
+// `rgb`, `rrggbb` or `rrggbbaa` with optional `#`
+pub(crate) fn parse_color(color: &str) -> Result<[u8; 4], QrError> {
+    let hex = color.trim_start_matches('#');
+    let error = || QrError::Color(color.to_string());
+    if !hex.is_ascii() {
+        return Err(error());
+    }
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect::<String>() + "ff",
+        6 => format!("{}ff", hex),
+        8 => hex.to_string(),
+        _ => return Err(error()),
+    };
+    let mut rgba = [0; 4];
+    for (i, channel) in rgba.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| error())?;
+    }
+    Ok(rgba)
+}
+
+// dark modules of the code with margin, row by row
+fn modules(data: &str, options: &QrOptions) -> Result<(usize, Vec<bool>), QrError> {
+    let code = QrCode::with_error_correction_level(data, options.level.into()).map_err(|_| QrError::TooLong)?;
+    let width = code.width();
+    let margin = options.margin.min(MAX_QR_MARGIN) as usize;
+    let full = width + margin * 2;
+    let mut dark = vec![false; full * full];
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            dark[(i / width + margin) * full + i % width + margin] = true;
+        }
+    }
+    Ok((full, dark))
+}
+
+pub(crate) fn png(data: &str, options: &QrOptions) -> Result<Vec<u8>, QrError> {
+    let (full, dark) = modules(data, options)?;
+    let scale = (options.size.min(MAX_QR_SIZE) as usize / full).max(1);
+    let side = full * scale;
+    let encode = |e: png::EncodingError| QrError::Encode(e.to_string());
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, side as u32, side as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(encode)?;
+    // rows are compressed as they are written, only the compressed image is kept in memory, not the raw pixels
+    let mut stream = writer.stream_writer().map_err(encode)?;
+    let mut row = Vec::with_capacity(side * 4);
+    for y in 0..full {
+        row.clear();
+        for x in 0..side {
+            row.extend_from_slice(if dark[y * full + x / scale] { &options.foreground } else { &options.background });
+        }
+        for _ in 0..scale {
+            stream.write_all(&row).map_err(|e| QrError::Encode(e.to_string()))?;
+        }
+    }
+    stream.finish().map_err(encode)?;
+    drop(writer);
+    Ok(out)
+}
+
+pub(crate) fn svg(data: &str, options: &QrOptions) -> Result<String, QrError> {
+    let (full, dark) = modules(data, options)?;
+    let mut path = String::new();
+    for (i, _) in dark.iter().enumerate().filter(|(_, d)| **d) {
+        path += &format!("M{},{}h1v1h-1z", i % full, i / full);
+    }
+    let size = options.size.min(MAX_QR_SIZE);
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" viewBox=\"0 0 {full} {full}\" shape-rendering=\"crispEdges\">\
+        <rect width=\"{full}\" height=\"{full}\" fill=\"{}\"/><path d=\"{path}\" fill=\"{}\"/></svg>\n",
+        svg_color(options.background),
+        svg_color(options.foreground),
+    ))
+}
+
+fn svg_color(rgba: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}{:02x}", rgba[0], rgba[1], rgba[2], rgba[3])
+}
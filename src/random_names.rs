@@ -0,0 +1,216 @@
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use harsh::Harsh;
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
+use mongodb::options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument};
+use rand::{Rng, SeedableRng};
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use crate::{COUNTERS_COLLECTION, Domain, config, connect};
+use crate::validation::{NameConfig, validate_name};
+
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Generator {
+    // random characters from `random_alphabet`
+    Alphabet,
+    // random words joined with `random_word_separator`
+    Words,
+    // counter in base62
+    Sequential,
+    // counter encoded with hashids
+    Hashids,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct RandomConfig {
+    pub(crate) random_generator: Generator,
+    // characters of generated names, grows when names collide too often
+    pub(crate) random_length: usize,
+    // words of generated names, grows the same way
+    pub(crate) random_words: usize,
+    // without ambiguous characters like `0/O` or `l/1/I` by default
+    pub(crate) random_alphabet: String,
+    pub(crate) random_word_separator: String,
+    // one word per line, bundled list is used when not set
+    pub(crate) random_wordlist_file: Option<String>,
+    pub(crate) hashids_salt: String,
+    // how many names are tried before giving up
+    pub(crate) random_max_tries: u32,
+    // share of colliding names which makes names one longer
+    pub(crate) random_collision_rate: f64,
+}
+
+impl Default for RandomConfig {
+    fn default() -> Self {
+        Self {
+            random_generator: Generator::Alphabet,
+            random_length: 8,
+            random_words: 3,
+            random_alphabet: "23456789abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ".to_string(),
+            random_word_separator: "-".to_string(),
+            random_wordlist_file: None,
+            hashids_salt: "".to_string(),
+            random_max_tries: 5,
+            random_collision_rate: 0.2,
+        }
+    }
+}
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+// names generated before collision rate is checked
+const COLLISION_WINDOW: usize = 20;
+// names don't get longer than `random_length` (or `random_words`) plus this
+const MAX_EXTRA_LENGTH: usize = 4;
+
+// `random_wordlist_file` or the bundled list, read once
+static WORDLIST_TEXT: LazyLock<String> = LazyLock::new(|| {
+    match config::load::<RandomConfig>().random_wordlist_file {
+        Some(path) => std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            println!("Could not read wordlist '{}': {:?}", path, e);
+            WORDLIST.to_string()
+        }),
+        None => WORDLIST.to_string(),
+    }
+});
+static WORDS: LazyLock<Vec<&'static str>> = LazyLock::new(|| WORDLIST_TEXT.lines().map(str::trim).filter(|w| !w.is_empty()).collect());
+
+// added to `random_length`, grows when collision rate gets too high and shrinks when it gets low again
+static EXTRA_LENGTH: AtomicUsize = AtomicUsize::new(0);
+static GENERATED: AtomicUsize = AtomicUsize::new(0);
+static COLLIDED: AtomicUsize = AtomicUsize::new(0);
+
+// free random name, none when all tries collided
+pub(crate) async fn random_name(col: &Collection<Domain>) -> Option<String> {
+    let conf = config::load::<RandomConfig>();
+    let names = config::load::<NameConfig>();
+    let words: &[&str] = match conf.random_generator {
+        Generator::Words => &WORDS,
+        _ => &[],
+    };
+    let options = FindOneOptions::builder().collation(names.collation()).build();
+    for _ in 0..conf.random_max_tries.max(1) {
+        let counter = match conf.random_generator {
+            Generator::Sequential | Generator::Hashids => next_counter().await?,
+            _ => 0,
+        };
+        // counters don't collide, their length stays as configured
+        let length = match conf.random_generator {
+            Generator::Words => conf.random_words.max(1) + EXTRA_LENGTH.load(Ordering::Relaxed),
+            Generator::Alphabet => conf.random_length.max(1) + EXTRA_LENGTH.load(Ordering::Relaxed),
+            Generator::Sequential | Generator::Hashids => conf.random_length.max(1),
+        };
+        let name = match generate(&conf, length, counter, words) {
+            Ok(n) => n,
+            Err(e) => {
+                println!("Could not generate random name: {}", e);
+                return None;
+            }
+        };
+        // random redirects are global, names can't clash with names or aliases there
+        let taken = doc! { "$or": [{ "name": &name }, { "aliases": &name }], "namespace": null };
+        let free = validate_name(&name, &names).is_ok() && match col.find_one(taken, options.clone()).await {
+            Ok(d) => d.is_none(),
+            Err(e) => {
+                println!("{:?}", e);
+                return None;
+            }
+        };
+        if matches!(conf.random_generator, Generator::Alphabet | Generator::Words) {
+            record(free, &conf);
+        }
+        if free {
+            return Some(name);
+        }
+    }
+    None
+}
+
+// makes names longer once too many of them collide, shorter again when few do
+fn record(free: bool, conf: &RandomConfig) {
+    let generated = GENERATED.fetch_add(1, Ordering::Relaxed) + 1;
+    let collided = if free { COLLIDED.load(Ordering::Relaxed) } else { COLLIDED.fetch_add(1, Ordering::Relaxed) + 1 };
+    if generated >= COLLISION_WINDOW {
+        let rate = collided as f64 / generated as f64;
+        let old = EXTRA_LENGTH.load(Ordering::Relaxed);
+        let extra = extra_length(old, rate, conf.random_collision_rate);
+        if extra != old {
+            EXTRA_LENGTH.store(extra, Ordering::Relaxed);
+            println!("Random names collide {:.0}% of the time, their extra length is now {}", rate * 100.0, extra);
+        }
+        GENERATED.store(0, Ordering::Relaxed);
+        COLLIDED.store(0, Ordering::Relaxed);
+    }
+}
+
+// one longer above `limit`, one shorter below half of it
+pub(crate) fn extra_length(extra: usize, rate: f64, limit: f64) -> usize {
+    if rate > limit {
+        (extra + 1).min(MAX_EXTRA_LENGTH)
+    } else if rate < limit / 2.0 {
+        extra.saturating_sub(1)
+    } else {
+        extra
+    }
+}
+
+async fn next_counter() -> Option<u64> {
+    let col = connect().await.collection::<Document>(COUNTERS_COLLECTION);
+    let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+    match col.find_one_and_update(doc! { "_id": "random" }, doc! { "$inc": { "value": 1_i64 } }, options).await {
+        Ok(Some(d)) => d.get_i64("value").ok().map(|v| v as u64),
+        Ok(None) => None,
+        Err(e) => {
+            println!("{:?}", e);
+            None
+        }
+    }
+}
+
+pub(crate) fn generate(conf: &RandomConfig, length: usize, counter: u64, words: &[&str]) -> Result<String, String> {
+    let mut rng = SmallRng::from_entropy();
+    match conf.random_generator {
+        Generator::Alphabet => {
+            let alphabet: Vec<char> = conf.random_alphabet.chars().collect();
+            if alphabet.is_empty() {
+                return Err("alphabet is empty".to_string());
+            }
+            Ok((0..length).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect())
+        }
+        Generator::Words => {
+            if words.is_empty() {
+                return Err("wordlist is empty".to_string());
+            }
+            let picked: Vec<&str> = (0..length).filter_map(|_| words.choose(&mut rng).copied()).collect();
+            Ok(picked.join(&conf.random_word_separator))
+        }
+        Generator::Sequential => Ok(base62(counter)),
+        Generator::Hashids => {
+            let harsh = Harsh::builder()
+                .salt(conf.hashids_salt.as_str())
+                .alphabet(conf.random_alphabet.as_str())
+                .length(length)
+                .build()
+                .map_err(|e| e.to_string())?;
+            Ok(harsh.encode(&[counter]))
+        }
+    }
+}
+
+pub(crate) fn base62(mut n: u64) -> String {
+    let mut out = vec![];
+    loop {
+        out.push(BASE62[(n % 62) as usize]);
+        n /= 62;
+        if n == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap_or_default()
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use mongodb::bson::Bson;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::Deserialize;
+use url::{Url, form_urlencoded};
+use crate::{DOMAIN, REDIRECT_PREFIX};
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub(crate) struct ShortHostConfig {
+    // misses on this host go here instead of `fallback_url`
+    pub(crate) fallback_url: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct HostsConfig {
+    // where misses go
+    pub(crate) fallback_url: String,
+    // hosts with their own names, e.g. `go.team-a`, other hosts use only global names
+    pub(crate) short_hosts: HashMap<String, ShortHostConfig>,
+}
+
+impl Default for HostsConfig {
+    fn default() -> Self {
+        Self {
+            fallback_url: DOMAIN.to_string(),
+            short_hosts: HashMap::new(),
+        }
+    }
+}
+
+impl HostsConfig {
+    // namespace of the request host, none for hosts using only global names
+    pub(crate) fn namespace(&self, host: Option<&str>) -> Option<String> {
+        let host = host?.trim_end_matches('.').to_lowercase();
+        self.short_hosts.keys().find(|h| h.to_lowercase() == host).map(|_| host)
+    }
+
+    pub(crate) fn fallback(&self, namespace: Option<&str>) -> String {
+        namespace
+            .and_then(|n| self.short_hosts.iter().find(|(h, _)| h.to_lowercase() == n))
+            .and_then(|(_, c)| c.fallback_url.clone())
+            .unwrap_or_else(|| self.fallback_url.clone())
+    }
+}
+
+// value of `namespace` field in filters, null matches global redirects
+pub(crate) fn namespace_bson(namespace: &Option<String>) -> Bson {
+    match namespace {
+        Some(n) => Bson::String(n.clone()),
+        None => Bson::Null,
+    }
+}
+
+// public url of a redirect, redirects of short hosts use their host
+pub(crate) fn short_url(public_url: &str, root_links: bool, name: &str, namespace: Option<&str>) -> String {
+    let mut url = match Url::parse(public_url) {
+        Ok(u) => u,
+        Err(_) => return format!("{}{}/{}", public_url.trim_end_matches('/'), REDIRECT_PREFIX, name),
+    };
+    if let Some(host) = namespace {
+        if url.set_host(Some(host)).is_ok() {
+            let _ = url.set_port(None);
+        }
+    }
+    if let Ok(mut path) = url.path_segments_mut() {
+        path.pop_if_empty();
+        if !root_links {
+            path.push(REDIRECT_PREFIX.trim_start_matches('/'));
+        }
+        path.push(name);
+    }
+    url.set_query(None);
+    url.to_string()
+}
+
+// host from `Host` header without port
+pub(crate) struct RequestHost(pub(crate) Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestHost {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestHost(req.host().map(|h| h.domain().to_string())))
+    }
+}
+
+// destination with remaining path appended and query merged, query of the request wins for same keys
+pub(crate) fn forward_url(destination: &str, rest: &[&str], query: Option<&str>) -> String {
+    let mut url = match Url::parse(destination) {
+        Ok(u) => u,
+        Err(_) => return destination.to_string(),
+    };
+    if !rest.is_empty() {
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(rest);
+        }
+    }
+    let query = merge_query(url.query(), query);
+    url.set_query(query.as_deref());
+    url.to_string()
+}
+
+fn merge_query(destination: Option<&str>, request: Option<&str>) -> Option<String> {
+    let request: Vec<&str> = request.unwrap_or_default().split('&').filter(|p| !p.is_empty()).collect();
+    let keys: Vec<&str> = request.iter().map(|p| query_key(p)).collect();
+    let merged: Vec<&str> = destination
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty() && !keys.contains(&query_key(p)))
+        .chain(request.iter().copied())
+        .collect();
+    if merged.is_empty() {
+        None
+    } else {
+        Some(merged.join("&"))
+    }
+}
+
+fn query_key(pair: &str) -> &str {
+    pair.split_once('=').map(|(k, _)| k).unwrap_or(pair)
+}
+
+// `a=1&b=2` with empty pairs dropped and values re-encoded, error names the pair without a key
+// pairs of `extra` replace the same keys of `params`
+pub(crate) fn build_params(params: &str, extra: &[(&str, &str)]) -> Result<String, String> {
+    let mut pairs: Vec<(String, String)> = vec![];
+    for pair in params.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = form_urlencoded::parse(pair.as_bytes()).next().unwrap_or_default();
+        if key.trim().is_empty() {
+            return Err(pair.to_string());
+        }
+        pairs.push((key.into_owned(), value.into_owned()));
+    }
+    for (key, value) in extra {
+        pairs.retain(|(k, _)| k != key);
+        pairs.push((key.to_string(), value.to_string()));
+    }
+    Ok(form_urlencoded::Serializer::new(String::new()).extend_pairs(pairs).finish())
+}
+
+// adds pairs of `params` whose keys the destination doesn't have yet
+pub(crate) fn tag_url(destination: &str, params: &str) -> String {
+    let mut url = match Url::parse(destination) {
+        Ok(u) => u,
+        Err(_) => return destination.to_string(),
+    };
+    let mut keys: Vec<String> = url.query_pairs().map(|(k, _)| k.into_owned()).collect();
+    let missing: Vec<(String, String)> = form_urlencoded::parse(params.as_bytes())
+        .filter(|(k, _)| {
+            let new = !k.is_empty() && !keys.iter().any(|e| e == k);
+            if new {
+                keys.push(k.to_string());
+            }
+            new
+        })
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if !missing.is_empty() {
+        url.query_pairs_mut().extend_pairs(missing);
+    }
+    url.to_string()
+}
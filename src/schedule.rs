@@ -0,0 +1,75 @@
+use std::sync::LazyLock;
+use chrono::{NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::{Domain, config};
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct ScheduleConfig {
+    // IANA name like `Europe/Warsaw`, times of schedules are wall clock times there
+    pub(crate) schedule_timezone: String,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            schedule_timezone: "UTC".to_string(),
+        }
+    }
+}
+
+static TIMEZONE: LazyLock<Tz> = LazyLock::new(|| {
+    let name = config::load::<ScheduleConfig>().schedule_timezone;
+    name.parse().unwrap_or_else(|_| {
+        println!("Unknown schedule_timezone '{}', using UTC", name);
+        Tz::UTC
+    })
+});
+
+// destination used from `start` until the next one starts
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, PartialEq)]
+pub(crate) struct Scheduled {
+    // e.g. `2026-11-02T09:00:00`
+    #[schemars(with = "String")]
+    pub(crate) start: NaiveDateTime,
+    pub(crate) domain: String,
+}
+
+// current wall clock time in `schedule_timezone`
+pub(crate) fn now() -> NaiveDateTime {
+    Utc::now().with_timezone(&*TIMEZONE).naive_local()
+}
+
+// `2026-11-02T09:00`, `2026-11-02T09:00:00` or `2026-11-02 09:00`
+pub(crate) fn parse_time(time: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(time.trim(), f).ok())
+}
+
+// none outside of the activation window, otherwise with the scheduled destination
+pub(crate) fn resolve(mut dom: Domain, now: NaiveDateTime) -> Option<Domain> {
+    if dom.active_from.is_some_and(|from| now < from) || dom.active_until.is_some_and(|until| now >= until) {
+        return None;
+    }
+    if let Some(d) = scheduled(&dom.schedule, dom.schedule_repeat_days, now) {
+        dom.domain = d.domain.clone();
+    }
+    Some(dom)
+}
+
+// entries are sorted by start, before the first one the redirect's own domain is used
+// repeating schedules start over `repeat_days` after the first entry
+pub(crate) fn scheduled(schedule: &[Scheduled], repeat_days: Option<u32>, now: NaiveDateTime) -> Option<&Scheduled> {
+    let first = schedule.first()?.start;
+    let now = match repeat_days.filter(|d| *d > 0) {
+        Some(days) if now >= first => {
+            let period = i64::from(days) * 24 * 60 * 60;
+            first + chrono::Duration::seconds((now - first).num_seconds() % period)
+        }
+        _ => now,
+    };
+    schedule.iter().rev().find(|s| s.start <= now)
+}
@@ -0,0 +1,282 @@
+use std::net::IpAddr;
+use std::sync::LazyLock;
+use maxminddb::{Reader, geoip2};
+use regex::Regex;
+use rocket::request::{FromRequest, Outcome, Request};
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use rand::Rng;
+use crate::{Domain, config};
+
+// languages of `Accept-Language` used for matching
+const MAX_LANGUAGES: usize = 10;
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub(crate) struct GeoConfig {
+    // MaxMind country or city database (`.mmdb`), countries of targets are ignored without it
+    pub(crate) geoip_database: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct VariantConfig {
+    // how long sticky variants are kept
+    pub(crate) variant_cookie_days: i64,
+}
+
+impl Default for VariantConfig {
+    fn default() -> Self {
+        Self {
+            variant_cookie_days: 30,
+        }
+    }
+}
+
+static GEOIP: LazyLock<Option<Reader<Vec<u8>>>> = LazyLock::new(|| {
+    let path = config::load::<GeoConfig>().geoip_database?;
+    match Reader::open_readfile(&path) {
+        Ok(r) => Some(r),
+        Err(e) => {
+            println!("Could not read GeoIP database '{}': {:?}", path, e);
+            None
+        }
+    }
+});
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Os {
+    Ios,
+    Android,
+    Windows,
+    Macos,
+    Linux,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Device {
+    Mobile,
+    Tablet,
+    #[default]
+    Desktop,
+    // crawlers and link previews
+    Bot,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Browser {
+    Chrome,
+    Firefox,
+    Safari,
+    Edge,
+    Opera,
+}
+
+// destination used when every condition which is set matches the visitor
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, PartialEq)]
+pub(crate) struct Target {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) os: Option<Os>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) device: Option<Device>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) browser: Option<Browser>,
+    // language tags like `de` (any German) or `pt-br`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) languages: Vec<String>,
+    // ISO 3166 codes like `PL`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) countries: Vec<String>,
+    pub(crate) domain: String,
+}
+
+// one of destinations traffic is split between
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, PartialEq)]
+pub(crate) struct Variant {
+    // label logged with clicks and kept in the sticky cookie, e.g. `a`
+    pub(crate) name: String,
+    pub(crate) weight: u32,
+    pub(crate) domain: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct UserAgent {
+    pub(crate) os: Option<Os>,
+    pub(crate) device: Device,
+    pub(crate) browser: Option<Browser>,
+}
+
+// what targets are matched against
+#[derive(Debug, Default)]
+pub(crate) struct Visitor {
+    pub(crate) agent: UserAgent,
+    // lowercase tags from `Accept-Language`, most preferred first
+    pub(crate) languages: Vec<String>,
+    // uppercase ISO code found in GeoIP database
+    pub(crate) country: Option<String>,
+    // connecting address, or forwarded one from a trusted proxy
+    pub(crate) ip: Option<IpAddr>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Visitor {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let agent = req.headers().get_one("User-Agent").map(parse_user_agent).unwrap_or_default();
+        let languages = req.headers().get_one("Accept-Language").map(parse_languages).unwrap_or_default();
+        let ip = crate::access::client_ip(req);
+        let country = ip.and_then(country);
+        Outcome::Success(Visitor { agent, languages, country, ip })
+    }
+}
+
+fn country(ip: IpAddr) -> Option<String> {
+    let reader = GEOIP.as_ref()?;
+    let found: geoip2::Country = reader.lookup(ip).ok()?;
+    found.country.and_then(|c| c.iso_code).map(str::to_uppercase)
+}
+
+// `de-AT,de;q=0.9,en;q=0.5` -> [de-at, de, en]
+pub(crate) fn parse_languages(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut parts = part.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q=").map(|q| q.parse::<f32>().unwrap_or(0.0)))
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && q > 0.0).then_some((tag, q))
+        })
+        .collect();
+    // stable, same weights keep order of the header
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().take(MAX_LANGUAGES).map(|(tag, _)| tag).collect()
+}
+
+// only the few things targets can use, unknown values stay none
+pub(crate) fn parse_user_agent(ua: &str) -> UserAgent {
+    let ua = ua.to_lowercase();
+    let has = |parts: &[&str]| parts.iter().any(|p| ua.contains(p));
+    // iOS agents contain `like mac os x`, Android ones `linux`
+    let os = if has(&["iphone", "ipad", "ipod"]) {
+        Some(Os::Ios)
+    } else if has(&["android"]) {
+        Some(Os::Android)
+    } else if has(&["windows"]) {
+        Some(Os::Windows)
+    } else if has(&["macintosh", "mac os x"]) {
+        Some(Os::Macos)
+    } else if has(&["linux", "x11", "cros"]) {
+        Some(Os::Linux)
+    } else {
+        None
+    };
+    let device = if has(&["bot", "crawler", "spider", "slurp", "facebookexternalhit"]) {
+        Device::Bot
+    } else if has(&["ipad", "tablet"]) || (os == Some(Os::Android) && !has(&["mobile"])) {
+        Device::Tablet
+    } else if has(&["mobi", "iphone", "ipod"]) {
+        Device::Mobile
+    } else {
+        Device::Desktop
+    };
+    // most agents name several browsers, more specific ones are checked first
+    let browser = if has(&["edg/", "edge/", "edga/", "edgios/"]) {
+        Some(Browser::Edge)
+    } else if has(&["opr/", "opera"]) {
+        Some(Browser::Opera)
+    } else if has(&["firefox/", "fxios/"]) {
+        Some(Browser::Firefox)
+    } else if has(&["chrome/", "crios/", "chromium/"]) {
+        Some(Browser::Chrome)
+    } else if has(&["safari/"]) {
+        Some(Browser::Safari)
+    } else {
+        None
+    };
+    UserAgent { os, device, browser }
+}
+
+impl Target {
+    // every condition except languages
+    fn matches(&self, visitor: &Visitor) -> bool {
+        let agent = &visitor.agent;
+        self.os.is_none_or(|os| agent.os == Some(os))
+            && self.device.is_none_or(|device| agent.device == device)
+            && self.browser.is_none_or(|browser| agent.browser == Some(browser))
+            && (self.countries.is_empty() || visitor.country.as_ref().is_some_and(|c| self.countries.contains(c)))
+    }
+
+    // `de` matches `de` and `de-at`, `de-at` only `de-at`
+    fn speaks(&self, language: &str) -> bool {
+        self.languages
+            .iter()
+            .any(|l| language == l || language.strip_prefix(l.as_str()).is_some_and(|rest| rest.starts_with('-')))
+    }
+
+    // lowercase languages and uppercase countries, error names what is not valid
+    pub(crate) fn normalized(mut self) -> Result<Self, String> {
+        let tag = Regex::new(r"^[a-z]{1,8}(-[a-z0-9]{1,8})*$").map_err(|e| e.to_string())?;
+        self.languages = self.languages.iter().map(|l| l.trim().to_lowercase()).collect();
+        self.countries = self.countries.iter().map(|c| c.trim().to_uppercase()).collect();
+        if let Some(l) = self.languages.iter().find(|l| !tag.is_match(l)) {
+            return Err(format!("language '{}'", l));
+        }
+        if let Some(c) = self.countries.iter().find(|c| c.len() != 2 || !c.chars().all(|c| c.is_ascii_uppercase())) {
+            return Err(format!("country '{}'", c));
+        }
+        Ok(self)
+    }
+}
+
+// target used for the visitor, none means the default destination
+// languages are tried in order of visitor's preference, then targets without languages in their order
+pub(crate) fn select<'a>(targets: &'a [Target], visitor: &Visitor) -> Option<&'a Target> {
+    visitor
+        .languages
+        .iter()
+        .find_map(|l| targets.iter().find(|t| t.speaks(l) && t.matches(visitor)))
+        .or_else(|| targets.iter().find(|t| t.languages.is_empty() && t.matches(visitor)))
+}
+
+// variant for a roll in `0..total weight`, sticky one wins while it still has weight
+pub(crate) fn pick_variant<'a>(variants: &'a [Variant], sticky: Option<&str>, roll: u64) -> Option<&'a Variant> {
+    if let Some(v) = variants.iter().find(|v| v.weight > 0 && Some(v.name.as_str()) == sticky) {
+        return Some(v);
+    }
+    let mut roll = roll;
+    variants.iter().find(|v| {
+        if roll < v.weight as u64 {
+            true
+        } else {
+            roll -= v.weight as u64;
+            false
+        }
+    })
+}
+
+pub(crate) fn total_weight(variants: &[Variant]) -> u64 {
+    variants.iter().map(|v| v.weight as u64).sum()
+}
+
+// destination for the visitor, matching target wins over variants which win over the default
+// returns name of the served variant
+pub(crate) fn choose(dom: &mut Domain, visitor: &Visitor, sticky: Option<&str>) -> Option<String> {
+    if let Some(t) = select(&dom.targets, visitor) {
+        dom.domain = t.domain.clone();
+        return None;
+    }
+    let total = total_weight(&dom.variants);
+    if total == 0 {
+        return None;
+    }
+    let roll = rand::thread_rng().gen_range(0..total);
+    let variant = pick_variant(&dom.variants, sticky, roll)?;
+    dom.domain = variant.domain.clone();
+    Some(variant.name.clone())
+}